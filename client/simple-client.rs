@@ -1,15 +1,17 @@
 use froggi::request::RequestKind;
 
 fn main() {
-    let addr = std::env::args()
+    let url = std::env::args()
         .nth(1)
-        .unwrap_or(String::from("127.0.0.1:11121"));
+        .unwrap_or(String::from("frgi://127.0.0.1/"));
+    let url = froggi::url::Url::parse(&url).unwrap();
 
-    let request = std::env::args().nth(2).unwrap_or(String::from(""));
+    println!("asking for {}", url);
 
-    println!("connecting to {}, asking for '{}'", addr, request);
+    let client = froggi::Client::builder().build().unwrap();
+    let (url, result) = client.get(&url, RequestKind::PageOnly).unwrap();
 
-    let result = froggi::send_request(addr, &request, RequestKind::PageOnly).unwrap();
+    println!("got {}", url);
 
     match result.parse() {
         Ok(page) => {
@@ -0,0 +1,87 @@
+//! Derive macros for the froggi protocol library.
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Expr, Fields};
+
+/// Derive `From<u8>` and `Into<u8>` for an enum of protocol values.
+///
+/// Every unit variant needs an explicit value, and there has to be an `Unknown(u8)` variant,
+/// which holds any byte that isn't one of the values. Nothing is lost when decoding, so
+/// encoding a decoded value always gives back the same byte. Enums with both need `#[repr(u8)]`.
+///
+/// `Unknown` shouldn't be used for a byte that has a variant, since it decodes as that variant.
+#[proc_macro_derive(U8Enum)]
+pub fn derive_u8_enum(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    u8_enum(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn u8_enum(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &input.ident;
+    let data = match &input.data {
+        Data::Enum(data) => data,
+        _ => {
+            return Err(Error::new_spanned(
+                input,
+                "U8Enum can only be derived for enums",
+            ))
+        }
+    };
+
+    let mut variants = Vec::new();
+    let mut values = Vec::new();
+    let mut unknown = false;
+
+    for variant in data.variants.iter() {
+        match (&variant.fields, &variant.discriminant) {
+            (Fields::Unit, Some((_, Expr::Lit(value)))) => {
+                variants.push(&variant.ident);
+                values.push(value);
+            }
+
+            (Fields::Unnamed(fields), None)
+                if variant.ident == "Unknown" && fields.unnamed.len() == 1 =>
+            {
+                unknown = true;
+            }
+
+            _ => {
+                return Err(Error::new_spanned(
+                    variant,
+                    "expected a variant like `Name = 0`, or `Unknown(u8)`",
+                ))
+            }
+        }
+    }
+
+    if !unknown {
+        return Err(Error::new(
+            Span::call_site(),
+            "U8Enum needs an `Unknown(u8)` variant",
+        ));
+    }
+
+    Ok(quote! {
+        impl ::core::convert::From<u8> for #name {
+            fn from(value: u8) -> #name {
+                match value {
+                    #(#values => #name::#variants,)*
+                    value => #name::Unknown(value),
+                }
+            }
+        }
+
+        impl ::core::convert::From<#name> for u8 {
+            fn from(value: #name) -> u8 {
+                match value {
+                    #(#name::#variants => #values,)*
+                    #name::Unknown(value) => value,
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,711 @@
+//! A reusable client with timeouts, client IDs, and redirect and limit policies.
+//!
+//! Unlike the `send_request` functions, a `Client` remembers the client ID each server hands
+//! out and sends it back on later requests to the same server, by scheme, host and port, so an ID
+//! from a TLS server never goes out in plaintext. It also remembers which servers only speak older
+//! versions of froggi, and talks to them in those versions from then on.
+
+use crate::compression::Compression;
+use crate::limits::DecodeLimits;
+use crate::redirect::RedirectPolicy;
+use crate::request::{Request, RequestKind};
+use crate::response::{ErrorCode, Response};
+use crate::transport::{Stream, Tcp, Transport};
+use crate::url::{Scheme, Url};
+use crate::{codec::Codec, ErrorKind, FroggiError, UrlError, Uuid};
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[cfg(feature = "tls")]
+use rustls::ClientConfig;
+#[cfg(feature = "tls")]
+use std::io::Write;
+#[cfg(feature = "tls")]
+use std::sync::Arc;
+
+/// The user agent clients send unless told otherwise.
+pub const DEFAULT_USER_AGENT: &str = concat!("froggi/", env!("CARGO_PKG_VERSION"));
+
+/// Sends requests for URLs, remembering the client ID each server hands out.
+///
+/// Create one with `Client::builder()`. Requests go over TCP unless the client is given another
+/// transport.
+#[derive(Debug)]
+pub struct Client<T = Tcp> {
+    transport: T,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    retries: usize,
+    redirects: RedirectPolicy,
+    limits: DecodeLimits,
+    user_agent: Option<String>,
+    version: u8,
+    compression: Vec<Compression>,
+    #[cfg(feature = "tls")]
+    tls: Option<Arc<ClientConfig>>,
+    ids: Mutex<HashMap<ServerKey, Uuid>>,
+    versions: Mutex<HashMap<ServerKey, u8>>,
+}
+
+impl Client {
+    /// Start configuring a client
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::default()
+    }
+}
+
+impl<T: Transport> Client<T> {
+    /// Request a URL, following redirects according to the client's policy.
+    ///
+    /// Returns the URL the final response came from, which relative links should be resolved
+    /// against, along with the response. Error responses from the server are returned as
+    /// `ErrorKind::StatusError`.
+    pub fn get(&self, url: &Url, kind: RequestKind) -> Result<(Url, Response), FroggiError> {
+        // we keep track of IDs for each host ourselves
+        crate::redirect::follow(url, Uuid::nil(), self.redirects, |url, _| {
+            self.send_to(url, |url, id| Request::new_with_id(url.request(), id, kind))
+        })
+    }
+
+    /// Request the part of a page at the URL's fragment, following redirects like `get`.
+    ///
+    /// The server sends only the expressions at the anchor and the items they reference. URLs
+    /// without a fragment get the whole page.
+    pub fn get_fragment(
+        &self,
+        url: &Url,
+        kind: RequestKind,
+    ) -> Result<(Url, Response), FroggiError> {
+        crate::redirect::follow(url, Uuid::nil(), self.redirects, |url, _| {
+            self.send_to(url, |url, id| match url.fragment() {
+                Some(fragment) => {
+                    Request::new_with_id(format!("{}#{}", url.request(), fragment), id, kind)
+                }
+                None => Request::new_with_id(url.request(), id, kind),
+            })
+        })
+    }
+
+    /// Request one item of the page at a URL by name, following redirects like `get`.
+    ///
+    /// Only the part of the item starting at `offset` is sent, up to `len` bytes if there's a
+    /// length, or to the end of the item if there isn't. The response says where the part goes
+    /// in the whole item, so large items can be loaded lazily or resumed.
+    pub fn get_item(
+        &self,
+        url: &Url,
+        name: &str,
+        offset: usize,
+        len: Option<usize>,
+    ) -> Result<(Url, Response), FroggiError> {
+        crate::redirect::follow(url, Uuid::nil(), self.redirects, |url, _| {
+            self.send_to(url, |url, id| {
+                Request::new_item(url.request(), id, name, offset, len)
+            })
+        })
+    }
+
+    /// Get the client ID the server at this URL gave us, if it gave us one
+    pub fn client_id(&self, url: &Url) -> Option<Uuid> {
+        // unwrap safety - we never panic while holding the lock
+        self.ids.lock().unwrap().get(&host_key(url)).copied()
+    }
+
+    /// Forget the client ID the server at this URL gave us
+    pub fn forget_client_id(&self, url: &Url) {
+        self.ids.lock().unwrap().remove(&host_key(url));
+    }
+
+    /// Get the version of froggi requests to the server at this URL are written in
+    pub fn version(&self, url: &Url) -> u8 {
+        self.versions
+            .lock()
+            .unwrap()
+            .get(&host_key(url))
+            .copied()
+            .unwrap_or(self.version)
+    }
+
+    // send a single request, keeping track of the client ID and version. the request is made
+    // again whenever the ID or version changes
+    fn send_to(
+        &self,
+        url: &Url,
+        new: impl Fn(&Url, Uuid) -> Result<Request, FroggiError>,
+    ) -> Result<Response, FroggiError> {
+        let id = self.client_id(url).unwrap_or_else(Uuid::nil);
+        let request = self.request(url, id, &new)?;
+        let oldest = crate::FROGGI_VERSIONS[0];
+        let mut response = match self.send_with_retries(url, &request) {
+            // servers from before version 1 don't look at the version, so rather than telling us
+            // they're too old they misread newer requests, and hang up or send something we can't
+            // read. if the oldest version works, we stick with it
+            Err(error) if request.version() > oldest && is_garbled(&error) => {
+                let retry = self.request(url, id, &new)?.with_version(oldest)?;
+                match self.send_with_retries(url, &retry) {
+                    Ok(response) => {
+                        self.set_version(url, oldest);
+                        response
+                    }
+                    Err(_) => return Err(error),
+                }
+            }
+            result => result?,
+        };
+
+        // the server is older than us, and answered in the newest version it speaks. servers
+        // that couldn't read the request at all answer in the oldest version, so those errors
+        // don't say anything about the server's version
+        let unreadable = matches!(
+            response.error_code(),
+            Some(ErrorCode::BadRequest) | Some(ErrorCode::TooLarge)
+        );
+        if response.version() < self.version(url) && !unreadable {
+            self.set_version(url, response.version());
+            response = self.send_with_retries(url, &self.request(url, id, &new)?)?;
+        }
+
+        // the server forgot about us, so try again as someone new
+        if let Some(ErrorCode::InvalidClientId) = response.error_code() {
+            if !id.is_nil() {
+                self.forget_client_id(url);
+                response = self.send_with_retries(url, &self.request(url, Uuid::nil(), &new)?)?;
+            }
+        }
+
+        if !response.id().is_nil() {
+            self.ids
+                .lock()
+                .unwrap()
+                .insert(host_key(url), response.id());
+        }
+
+        response.error_for_status()
+    }
+
+    fn set_version(&self, url: &Url, version: u8) {
+        self.versions.lock().unwrap().insert(host_key(url), version);
+    }
+
+    fn request(
+        &self,
+        url: &Url,
+        id: Uuid,
+        new: &impl Fn(&Url, Uuid) -> Result<Request, FroggiError>,
+    ) -> Result<Request, FroggiError> {
+        let mut request = new(url, id)?.with_version(self.version(url))?;
+        if !self.compression.is_empty() {
+            request = request.with_accepted_compression(&self.compression)?;
+        }
+
+        match &self.user_agent {
+            Some(user_agent) => request.with_user_agent(user_agent),
+            None => Ok(request),
+        }
+    }
+
+    // connection problems and timeouts are worth another try, anything else isn't
+    fn send_with_retries(&self, url: &Url, request: &Request) -> Result<Response, FroggiError> {
+        let mut tries = 0;
+        loop {
+            match self.send_once(url, request) {
+                Err(error)
+                    if tries < self.retries
+                        && matches!(error.kind(), ErrorKind::IOError { .. }) =>
+                {
+                    tries += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn send_once(&self, url: &Url, request: &Request) -> Result<Response, FroggiError> {
+        let (host, port) = url.socket_addr();
+        let mut stream = self.transport.connect(host, port)?;
+        stream.set_timeouts(self.read_timeout, self.write_timeout)?;
+
+        match url.scheme() {
+            Scheme::Frgi => {
+                request.write_to(&mut stream)?;
+                Response::from_bytes_with_limits(&mut stream, self.limits)
+            }
+
+            #[cfg(feature = "tls")]
+            Scheme::Frgis if self.tls.is_some() => {
+                // unwrap safety - we just checked
+                let config = self.tls.clone().unwrap();
+                let mut stream = crate::tls::client_stream(stream, url.host(), config)?;
+
+                request.write_to(&mut stream)?;
+                stream.flush()?;
+                Response::from_bytes_with_limits(&mut stream, self.limits)
+            }
+
+            Scheme::Frgis => Err(FroggiError::new(ErrorKind::UrlError {
+                error: UrlError::TlsRequired {
+                    url: url.to_string(),
+                },
+            })),
+        }
+    }
+}
+
+// whether an error could have come from a server that misread a request in a newer version
+fn is_garbled(error: &FroggiError) -> bool {
+    matches!(
+        error.kind(),
+        ErrorKind::IOError { .. }
+            | ErrorKind::MagicError { .. }
+            | ErrorKind::ResponseFormatError
+            | ErrorKind::LengthMismatchError { .. }
+            | ErrorKind::VarintError { .. }
+            | ErrorKind::EncodingError { .. }
+    )
+}
+
+// what we remember about a server is only used for URLs with the same scheme, host and port
+type ServerKey = (Scheme, String, u16);
+
+fn host_key(url: &Url) -> ServerKey {
+    (url.scheme(), url.host().to_string(), url.port())
+}
+
+/// Configures a `Client`.
+///
+/// By default, requests go over TCP, connecting times out after 10 seconds, reading and writing
+/// after 30, failed connections are retried once, the default redirect policy and decode limits
+/// are used, requests are written in the newest version of froggi, and servers are told they can
+/// compress responses with any codec we support.
+#[derive(Debug)]
+pub struct ClientBuilder<T = Tcp> {
+    transport: T,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    retries: usize,
+    redirects: RedirectPolicy,
+    limits: DecodeLimits,
+    user_agent: Option<String>,
+    version: u8,
+    compression: Vec<Compression>,
+    #[cfg(feature = "tls")]
+    tls: Option<Arc<ClientConfig>>,
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        Self {
+            transport: Tcp::new().with_connect_timeout(Some(Duration::from_secs(10))),
+            read_timeout: Some(Duration::from_secs(30)),
+            write_timeout: Some(Duration::from_secs(30)),
+            retries: 1,
+            redirects: RedirectPolicy::default(),
+            limits: DecodeLimits::default(),
+            user_agent: Some(DEFAULT_USER_AGENT.into()),
+            version: crate::FROGGI_VERSION,
+            compression: Compression::supported().to_vec(),
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+}
+
+impl ClientBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How long to wait for a connection, or `None` to wait forever
+    pub fn connect_timeout(self, connect_timeout: Option<Duration>) -> Self {
+        Self {
+            transport: self.transport.with_connect_timeout(connect_timeout),
+            ..self
+        }
+    }
+}
+
+impl<T: Transport> ClientBuilder<T> {
+    pub fn build(self) -> Result<Client<T>, FroggiError> {
+        Codec::for_version(self.version)?;
+
+        // servers may use any codec we accept, so we have to be able to decompress it
+        if let Some(&compression) = self
+            .compression
+            .iter()
+            .find(|compression| !Compression::supported().contains(compression))
+        {
+            return Err(FroggiError::new(ErrorKind::CompressionError {
+                compression,
+            }));
+        }
+
+        // make sure the user agent fits in a request
+        if let Some(user_agent) = &self.user_agent {
+            Request::new("", RequestKind::PageOnly)?.with_user_agent(user_agent)?;
+        }
+
+        Ok(Client {
+            transport: self.transport,
+            read_timeout: self.read_timeout,
+            write_timeout: self.write_timeout,
+            retries: self.retries,
+            redirects: self.redirects,
+            limits: self.limits,
+            user_agent: self.user_agent,
+            version: self.version,
+            compression: self.compression,
+            #[cfg(feature = "tls")]
+            tls: self.tls,
+            ids: Mutex::new(HashMap::new()),
+            versions: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Send requests over something other than TCP
+    pub fn transport<U: Transport>(self, transport: U) -> ClientBuilder<U> {
+        ClientBuilder {
+            transport,
+            read_timeout: self.read_timeout,
+            write_timeout: self.write_timeout,
+            retries: self.retries,
+            redirects: self.redirects,
+            limits: self.limits,
+            user_agent: self.user_agent,
+            version: self.version,
+            compression: self.compression,
+            #[cfg(feature = "tls")]
+            tls: self.tls,
+        }
+    }
+
+    /// How long to wait for the server to send something, or `None` to wait forever
+    pub fn read_timeout(self, read_timeout: Option<Duration>) -> Self {
+        Self {
+            read_timeout,
+            ..self
+        }
+    }
+
+    /// How long to wait for the server to accept what we send, or `None` to wait forever
+    pub fn write_timeout(self, write_timeout: Option<Duration>) -> Self {
+        Self {
+            write_timeout,
+            ..self
+        }
+    }
+
+    /// How many more times to try a request if the connection fails or times out
+    pub fn retries(self, retries: usize) -> Self {
+        Self { retries, ..self }
+    }
+
+    /// Which redirects to follow
+    pub fn redirects(self, redirects: RedirectPolicy) -> Self {
+        Self { redirects, ..self }
+    }
+
+    /// The largest responses to accept
+    pub fn limits(self, limits: DecodeLimits) -> Self {
+        Self { limits, ..self }
+    }
+
+    /// What to tell servers the client is called, or `None` to not tell them
+    pub fn user_agent(self, user_agent: Option<String>) -> Self {
+        Self { user_agent, ..self }
+    }
+
+    /// The newest version of froggi to write requests in. Servers that only speak older versions
+    /// are talked to in those instead.
+    pub fn version(self, version: u8) -> Self {
+        Self { version, ..self }
+    }
+
+    /// The compression codecs servers may use for responses, most preferred first. Empty to
+    /// only accept uncompressed responses.
+    pub fn compression(self, compression: Vec<Compression>) -> Self {
+        Self {
+            compression,
+            ..self
+        }
+    }
+
+    /// The TLS configuration to request `frgis://` URLs with. Without one, they can't be
+    /// requested.
+    #[cfg(feature = "tls")]
+    pub fn tls(self, config: Arc<ClientConfig>) -> Self {
+        Self {
+            tls: Some(config),
+            ..self
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::response::{Item, ItemKind, ItemRange, ResponseBuilder};
+
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::mpsc;
+    use std::thread;
+
+    // answer a number of connections, telling the test which requests came in
+    fn serve(
+        connections: usize,
+        mut respond: impl FnMut(&Request) -> Response + Send + 'static,
+    ) -> (Url, mpsc::Receiver<Request>) {
+        serve_with(connections, move |request, stream| {
+            respond(request).write_to(stream).unwrap()
+        })
+    }
+
+    // the same, but the test writes the response itself
+    fn serve_with(
+        connections: usize,
+        mut respond: impl FnMut(&Request, &mut TcpStream) + Send + 'static,
+    ) -> (Url, mpsc::Receiver<Request>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!(
+            "frgi://127.0.0.1:{}/",
+            listener.local_addr().unwrap().port()
+        );
+        let (send, receive) = mpsc::channel();
+
+        thread::spawn(move || {
+            for stream in listener.incoming().take(connections) {
+                let mut stream = stream.unwrap();
+                let request = Request::from_bytes(&mut stream).unwrap();
+                respond(&request, &mut stream);
+                send.send(request).unwrap();
+            }
+        });
+
+        (Url::parse(&url).unwrap(), receive)
+    }
+
+    fn page_with_id(id: Uuid) -> Response {
+        ResponseBuilder::default()
+            .id(id)
+            .page("('hi')".into())
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn client_ids() {
+        let issued = Uuid::new_v4();
+        let (url, requests) = serve(2, move |_| page_with_id(issued));
+
+        let client = Client::builder().build().unwrap();
+        let (_, response) = client.get(&url, RequestKind::Page).unwrap();
+        assert_eq!(response.page(), "('hi')");
+        assert_eq!(client.client_id(&url), Some(issued));
+        client.get(&url, RequestKind::Page).unwrap();
+
+        let first = requests.recv().unwrap();
+        assert!(first.id().is_nil());
+        assert_eq!(first.user_agent(), Some(DEFAULT_USER_AGENT));
+        assert_eq!(requests.recv().unwrap().id(), issued);
+
+        // the same host over TLS is a different server
+        let tls = Url::parse(&url.to_string().replacen("frgi://", "frgis://", 1)).unwrap();
+        assert_eq!(tls.host(), url.host());
+        assert_eq!(client.client_id(&tls), None);
+    }
+
+    #[test]
+    fn forgotten_client_id() {
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut issued = vec![second, first];
+        let (url, requests) = serve(3, move |request| {
+            if request.id().is_nil() {
+                page_with_id(issued.pop().unwrap())
+            } else {
+                // the server restarted and doesn't know anyone
+                ResponseBuilder::default()
+                    .error(ErrorCode::InvalidClientId)
+                    .build()
+                    .unwrap()
+            }
+        });
+
+        let client = Client::builder().user_agent(None).build().unwrap();
+        client.get(&url, RequestKind::Page).unwrap();
+        assert_eq!(client.client_id(&url), Some(first));
+        client.get(&url, RequestKind::Page).unwrap();
+        assert_eq!(client.client_id(&url), Some(second));
+
+        let requests = requests.iter().collect::<Vec<_>>();
+        let ids = requests.iter().map(Request::id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![Uuid::nil(), first, Uuid::nil()]);
+        assert!(requests
+            .iter()
+            .all(|request| request.user_agent().is_none()));
+    }
+
+    #[test]
+    fn older_server() {
+        let (url, requests) = serve(3, |request| {
+            if request.version() == 0 {
+                page_with_id(Uuid::nil())
+            } else {
+                ResponseBuilder::default()
+                    .version(0)
+                    .error(ErrorCode::UnsupportedVersion)
+                    .build()
+                    .unwrap()
+            }
+        });
+
+        // the server answers in the version it speaks, and we switch to it
+        let client = Client::builder().build().unwrap();
+        assert_eq!(client.version(&url), crate::FROGGI_VERSION);
+        let (_, response) = client.get(&url, RequestKind::Page).unwrap();
+        assert_eq!(response.page(), "('hi')");
+        assert_eq!(client.version(&url), 0);
+
+        // and keep using it
+        client.get(&url, RequestKind::Page).unwrap();
+        let versions = requests.iter().map(|request| request.version());
+        assert_eq!(versions.collect::<Vec<_>>(), vec![1, 0, 0]);
+
+        // which doesn't say anything about the same host over TLS
+        let tls = Url::parse(&url.to_string().replacen("frgi://", "frgis://", 1)).unwrap();
+        assert_eq!(client.version(&tls), crate::FROGGI_VERSION);
+
+        assert!(Client::builder().version(2).build().is_err());
+    }
+
+    #[test]
+    fn server_from_before_versions() {
+        // hangs up on requests it can't make sense of, including the retry
+        let (url, requests) = serve_with(4, |request, stream| {
+            if request.version() == 0 {
+                page_with_id(Uuid::nil()).write_to(stream).unwrap();
+            }
+        });
+
+        let client = Client::builder().build().unwrap();
+        let (_, response) = client.get(&url, RequestKind::Page).unwrap();
+        assert_eq!(response.page(), "('hi')");
+        assert_eq!(client.version(&url), 0);
+
+        client.get(&url, RequestKind::Page).unwrap();
+        let versions = requests.iter().map(|request| request.version());
+        assert_eq!(versions.collect::<Vec<_>>(), vec![1, 1, 0, 0]);
+
+        // or answers them in the only version it knows, as if they were something else
+        let (url, requests) = serve(2, |_| {
+            ResponseBuilder::default()
+                .version(0)
+                .error(ErrorCode::NotFound)
+                .build()
+                .unwrap()
+        });
+        let client = Client::builder().build().unwrap();
+        assert!(client.get(&url, RequestKind::Page).is_err());
+        assert_eq!(client.version(&url), 0);
+        let versions = requests.iter().map(|request| request.version());
+        assert_eq!(versions.collect::<Vec<_>>(), vec![1, 0]);
+    }
+
+    #[test]
+    fn compression() {
+        let page = "('ribbit')".repeat(100);
+        let response = ResponseBuilder::default()
+            .page(page.clone())
+            .build()
+            .unwrap();
+        let (url, requests) = serve_with(1, move |request, stream| {
+            response.write_for(request, Uuid::nil(), stream).unwrap()
+        });
+
+        // compressed responses are decompressed without anyone noticing
+        let client = Client::builder().build().unwrap();
+        assert_eq!(client.get(&url, RequestKind::Page).unwrap().1.page(), page);
+        assert_eq!(
+            requests.recv().unwrap().accepted_compression(),
+            Compression::supported()
+        );
+
+        assert!(Client::builder()
+            .compression(vec![Compression::Unknown(9)])
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn item_ranges() {
+        let item = Item::new("big.png".into(), ItemKind::Image, b"ribbit".to_vec());
+        let (url, requests) = serve(2, move |request| {
+            ResponseBuilder::default()
+                .item_range(&item, request.item_offset(), request.item_len())
+                .build()
+                .unwrap()
+        });
+        let url = url.resolve("index.fml").unwrap();
+
+        let client = Client::builder().build().unwrap();
+        let (_, response) = client.get_item(&url, "big.png", 2, Some(3)).unwrap();
+        assert_eq!(response.items()[0].data(), b"bbi");
+        assert_eq!(
+            response.item_range(),
+            Some(ItemRange { offset: 2, size: 6 })
+        );
+
+        // and the rest of it
+        let (_, response) = client.get_item(&url, "big.png", 5, None).unwrap();
+        assert_eq!(response.items()[0].data(), b"t");
+
+        let request = requests.recv().unwrap();
+        assert_eq!(request.kind(), RequestKind::Item);
+        assert_eq!(request.request(), "index.fml");
+        assert_eq!(request.item_name(), "big.png");
+    }
+
+    #[test]
+    fn fragments() {
+        let (url, requests) = serve(2, |_| page_with_id(Uuid::nil()));
+        let client = Client::builder().build().unwrap();
+
+        let url = url.resolve("index.fml#pond").unwrap();
+        client.get_fragment(&url, RequestKind::PageOnly).unwrap();
+        let request = requests.recv().unwrap();
+        assert_eq!(request.request(), "index.fml#pond");
+        assert_eq!(request.path(), "index.fml");
+        assert_eq!(request.fragment(), Some("pond"));
+
+        // the fragment is only for scrolling otherwise
+        client.get(&url, RequestKind::PageOnly).unwrap();
+        let request = requests.recv().unwrap();
+        assert_eq!(request.path(), "index.fml");
+        assert_eq!(request.fragment(), None);
+    }
+
+    #[test]
+    fn timeout() {
+        // a server that never responds
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!(
+            "frgi://127.0.0.1:{}/",
+            listener.local_addr().unwrap().port()
+        );
+        let url = Url::parse(&url).unwrap();
+        thread::spawn(move || {
+            let streams = listener.incoming().take(2).collect::<Vec<_>>();
+            thread::sleep(Duration::from_secs(5));
+            drop(streams);
+        });
+
+        let client = Client::builder()
+            .read_timeout(Some(Duration::from_millis(50)))
+            .build()
+            .unwrap();
+        assert!(matches!(
+            client.get(&url, RequestKind::PageOnly).unwrap_err().kind(),
+            ErrorKind::IOError { .. }
+        ));
+    }
+}
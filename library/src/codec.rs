@@ -0,0 +1,306 @@
+//! The parts of the wire format that differ between froggi versions.
+//!
+//! Requests and responses are laid out the same way in every version, but the lengths in them
+//! aren't. Version 0 has fixed-width lengths, which cap paths at 64 KiB, responses at 255 items
+//! with names of up to 255 bytes, and everything at 4 GiB. Version 1 writes lengths as varints
+//! (unsigned LEB128), except for the total response length, which is eight bytes so it can be
+//! read before anything else.
+//!
+//! A `Codec` is picked from the version byte in the header, and knows how to read and write each
+//! length in that version.
+
+use crate::protocol::*;
+use crate::{ErrorKind, FroggiError};
+
+use std::convert::TryInto;
+use std::io::Read;
+
+#[cfg(feature = "async")]
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// A length on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    /// The length of a request's path
+    Path,
+    /// The length of a put request's body
+    Body,
+    /// The number of known item hashes in a request
+    KnownItems,
+    /// The total length of a response
+    Total,
+    /// The length of a response's page
+    Page,
+    /// The number of items in a response
+    Items,
+    /// The length of an item's name
+    ItemName,
+    /// The length of an item's media type
+    MediaType,
+    /// The length of an item's data
+    Item,
+    /// The offset of part of an item
+    Offset,
+}
+
+// how a length is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Width {
+    Fixed(usize),
+    Varint,
+}
+
+/// Reads and writes lengths the way one version of froggi does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    /// Fixed-width lengths
+    V0,
+    /// Varint lengths
+    V1,
+}
+
+impl Codec {
+    /// Get the codec for a version, failing with a `VersionError` if we don't speak it.
+    pub fn for_version(version: u8) -> Result<Codec, FroggiError> {
+        match version {
+            0 => Ok(Codec::V0),
+            1 => Ok(Codec::V1),
+            _ => Err(FroggiError::new(ErrorKind::VersionError { got: version })),
+        }
+    }
+
+    /// Get the version this codec is for
+    pub fn version(self) -> u8 {
+        match self {
+            Codec::V0 => 0,
+            Codec::V1 => 1,
+        }
+    }
+
+    /// Get the largest length that fits in a field
+    pub fn max(self, field: Field) -> usize {
+        match self.width(field) {
+            Width::Fixed(len) if len < std::mem::size_of::<usize>() => (1usize << (len * 8)) - 1,
+            _ => usize::MAX,
+        }
+    }
+
+    /// Get the number of bytes a length takes up on the wire
+    pub fn len_of(self, field: Field, len: usize) -> usize {
+        match self.width(field) {
+            Width::Fixed(width) => width,
+            Width::Varint => varint_len(len as u64),
+        }
+    }
+
+    fn width(self, field: Field) -> Width {
+        match (self, field) {
+            (Codec::V0, Field::Path) => Width::Fixed(REQUEST_LENGTH_LEN),
+            (Codec::V0, Field::Body) => Width::Fixed(REQUEST_BODY_LENGTH_LEN),
+            (Codec::V0, Field::KnownItems) => Width::Fixed(KNOWN_ITEMS_COUNT_LEN),
+            (Codec::V0, Field::Total) => Width::Fixed(TOTAL_RESPONSE_LENGTH_LEN),
+            (Codec::V0, Field::Page) => Width::Fixed(PAGE_LENGTH_LEN),
+            (Codec::V0, Field::Items) => Width::Fixed(NUM_ITEMS_LEN),
+            (Codec::V0, Field::ItemName) => Width::Fixed(ITEM_NAME_LENGTH_LEN),
+            (Codec::V0, Field::MediaType) => Width::Fixed(MEDIA_TYPE_LENGTH_LEN),
+            (Codec::V0, Field::Item) => Width::Fixed(ITEM_LENGTH_LEN),
+            (Codec::V0, Field::Offset) => Width::Fixed(ITEM_OFFSET_LEN),
+            (Codec::V1, Field::Total) => Width::Fixed(TOTAL_RESPONSE_LENGTH_LEN_V1),
+            (Codec::V1, _) => Width::Varint,
+        }
+    }
+
+    /// Check that a length fits in a field.
+    pub(crate) fn check(self, field: Field, len: usize) -> Result<(), FroggiError> {
+        if len > self.max(field) {
+            let wanted = match self.width(field) {
+                Width::Fixed(width) => width * 8,
+                Width::Varint => 64,
+            };
+
+            return Err(FroggiError::new(ErrorKind::BitWidthError {
+                wanted,
+                got: len,
+            }));
+        }
+
+        Ok(())
+    }
+
+    /// Write a length, failing if it doesn't fit in the field.
+    pub(crate) fn write(
+        self,
+        field: Field,
+        len: usize,
+        out: &mut Vec<u8>,
+    ) -> Result<(), FroggiError> {
+        self.check(field, len)?;
+
+        match self.width(field) {
+            Width::Fixed(width) => out.extend_from_slice(&(len as u64).to_le_bytes()[..width]),
+
+            Width::Varint => {
+                let mut len = len as u64;
+                while len >= 0x80 {
+                    out.push(len as u8 | 0x80);
+                    len >>= 7;
+                }
+                out.push(len as u8);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Read a length.
+    pub(crate) fn read(self, field: Field, reader: &mut impl Read) -> Result<usize, FroggiError> {
+        match self.width(field) {
+            Width::Fixed(width) => {
+                let mut bytes = [0u8; 8];
+                reader.read_exact(&mut bytes[..width])?;
+                to_usize(u64::from_le_bytes(bytes))
+            }
+
+            Width::Varint => {
+                let mut varint = Varint::default();
+                loop {
+                    let mut byte = [0u8];
+                    reader.read_exact(&mut byte)?;
+                    if let Some(len) = varint.push(byte[0])? {
+                        return to_usize(len);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Read a length, keeping the bytes it was made of.
+    #[cfg(feature = "async")]
+    pub(crate) async fn read_async(
+        self,
+        field: Field,
+        reader: &mut (impl AsyncRead + Unpin),
+        data: &mut Vec<u8>,
+    ) -> Result<usize, FroggiError> {
+        match self.width(field) {
+            Width::Fixed(width) => {
+                let offset = data.len();
+                data.resize(offset + width, 0);
+                reader.read_exact(&mut data[offset..]).await?;
+                self.read(field, &mut &data[offset..])
+            }
+
+            Width::Varint => {
+                let mut varint = Varint::default();
+                loop {
+                    let byte = reader.read_u8().await?;
+                    data.push(byte);
+                    if let Some(len) = varint.push(byte)? {
+                        return to_usize(len);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn varint_len(mut len: u64) -> usize {
+    let mut bytes = 1;
+    while len >= 0x80 {
+        len >>= 7;
+        bytes += 1;
+    }
+    bytes
+}
+
+// lengths that don't fit in memory are certainly too large to read
+fn to_usize(len: u64) -> Result<usize, FroggiError> {
+    len.try_into().map_err(|_| {
+        FroggiError::new(ErrorKind::BitWidthError {
+            wanted: std::mem::size_of::<usize>() * 8,
+            got: usize::MAX,
+        })
+    })
+}
+
+// decodes a varint a byte at a time. only the shortest encoding of each number is accepted, so
+// that every length has exactly one encoding
+#[derive(Default)]
+struct Varint {
+    value: u64,
+    bytes: usize,
+}
+
+impl Varint {
+    fn push(&mut self, byte: u8) -> Result<Option<u64>, FroggiError> {
+        let bits = u64::from(byte & 0x7f);
+        let shift = self.bytes * 7;
+        self.bytes += 1;
+
+        let overflows = shift >= 64 || (bits << shift) >> shift != bits;
+        let padded = self.bytes > 1 && byte == 0;
+        if overflows || padded {
+            return Err(FroggiError::new(ErrorKind::VarintError {
+                bytes: self.bytes,
+            }));
+        }
+
+        self.value |= bits << shift;
+        if byte & 0x80 == 0 {
+            Ok(Some(self.value))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn encode(codec: Codec, field: Field, len: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        codec.write(field, len, &mut bytes).unwrap();
+        assert_eq!(bytes.len(), codec.len_of(field, len));
+        assert_eq!(codec.read(field, &mut bytes.as_slice()).unwrap(), len);
+        bytes
+    }
+
+    #[test]
+    fn fixed() {
+        assert_eq!(encode(Codec::V0, Field::Path, 0x1234), [0x34, 0x12]);
+        assert_eq!(encode(Codec::V0, Field::Items, 3), [3]);
+        assert_eq!(encode(Codec::V1, Field::Total, 1), [1, 0, 0, 0, 0, 0, 0, 0]);
+
+        assert_eq!(Codec::V0.max(Field::ItemName), 255);
+        assert!(Codec::V0.write(Field::Items, 256, &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn varints() {
+        assert_eq!(encode(Codec::V1, Field::Items, 0), [0]);
+        assert_eq!(encode(Codec::V1, Field::Items, 0x7f), [0x7f]);
+        assert_eq!(encode(Codec::V1, Field::Items, 0x80), [0x80, 0x01]);
+        assert_eq!(encode(Codec::V1, Field::Path, 300), [0xac, 0x02]);
+        assert_eq!(
+            encode(Codec::V1, Field::Item, u32::MAX as usize + 1).len(),
+            5
+        );
+        assert_eq!(Codec::V1.max(Field::ItemName), usize::MAX);
+
+        // padded, too long, and cut short
+        assert!(Codec::V1.read(Field::Page, &mut &[0x80, 0x00][..]).is_err());
+        assert!(Codec::V1.read(Field::Page, &mut &[0xff; 11][..]).is_err());
+        assert!(Codec::V1.read(Field::Page, &mut &[0x80][..]).is_err());
+    }
+
+    #[test]
+    fn versions() {
+        assert_eq!(Codec::for_version(0).unwrap(), Codec::V0);
+        assert_eq!(Codec::for_version(1).unwrap().version(), 1);
+        assert!(matches!(
+            Codec::for_version(2).unwrap_err().kind(),
+            ErrorKind::VersionError { got: 2 }
+        ));
+    }
+}
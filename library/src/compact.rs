@@ -0,0 +1,49 @@
+//! Compact serde encodings for bytes.
+//!
+//! Item data, request bodies and extension values are written as base64 strings in
+//! human-readable formats like JSON, rather than as arrays of numbers. Binary formats get the
+//! bytes as they are.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::de::{self, Deserializer, Visitor};
+use serde::Serializer;
+
+use std::fmt;
+
+pub(crate) fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    if serializer.is_human_readable() {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    } else {
+        serializer.serialize_bytes(bytes)
+    }
+}
+
+pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    if deserializer.is_human_readable() {
+        deserializer.deserialize_str(BytesVisitor)
+    } else {
+        deserializer.deserialize_byte_buf(BytesVisitor)
+    }
+}
+
+struct BytesVisitor;
+
+impl<'de> Visitor<'de> for BytesVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "base64 or bytes")
+    }
+
+    fn visit_str<E: de::Error>(self, string: &str) -> Result<Vec<u8>, E> {
+        STANDARD.decode(string).map_err(E::custom)
+    }
+
+    fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Vec<u8>, E> {
+        Ok(bytes.to_vec())
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, bytes: Vec<u8>) -> Result<Vec<u8>, E> {
+        Ok(bytes)
+    }
+}
@@ -0,0 +1,251 @@
+//! Compressing pages and items on the wire.
+//!
+//! Clients list the codecs they can decompress in an extension, and servers may compress the page
+//! and each item of their response with one of them. Each part says which codec it was compressed
+//! with, so parts that don't get any smaller can be sent as they are.
+//!
+//! Compressing and decompressing needs the `compression` feature. Without it, nothing is
+//! compressed, and compressed responses can't be read.
+
+use crate::limits::{DecodeLimits, Limit};
+use crate::{AddMsg, ErrorKind, FroggiError};
+use froggi_derive::U8Enum;
+
+use std::io::Read;
+#[cfg(feature = "compression")]
+use std::io::Write;
+
+/// How part of a response was compressed.
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, U8Enum)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Compression {
+    /// Not compressed at all.
+    None = 0,
+    /// Raw deflate, without a zlib or gzip header.
+    Deflate = 1,
+    /// Zstandard.
+    Zstd = 2,
+    /// A codec we don't know about.
+    Unknown(u8),
+}
+
+impl Compression {
+    /// Get the codecs we can compress and decompress with, most preferred first.
+    ///
+    /// Empty without the `compression` feature.
+    pub fn supported() -> &'static [Compression] {
+        #[cfg(feature = "compression")]
+        {
+            &[Compression::Zstd, Compression::Deflate]
+        }
+
+        #[cfg(not(feature = "compression"))]
+        {
+            &[]
+        }
+    }
+
+    /// Pick a codec to compress with for a client that accepts these, in its order of
+    /// preference.
+    ///
+    /// Returns `Compression::None` if we don't support any of them.
+    pub fn negotiate(accepted: &[Compression]) -> Compression {
+        accepted
+            .iter()
+            .copied()
+            .find(|compression| Compression::supported().contains(compression))
+            .unwrap_or(Compression::None)
+    }
+}
+
+/// Compress some data, or return `None` if compressing it doesn't make it any smaller.
+pub fn compress(compression: Compression, data: &[u8]) -> Result<Option<Vec<u8>>, FroggiError> {
+    let compressed: Option<Vec<u8>> = match compression {
+        Compression::None => None,
+
+        #[cfg(feature = "compression")]
+        Compression::Deflate => {
+            let mut encoder =
+                flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(data)?;
+            Some(encoder.finish()?)
+        }
+
+        #[cfg(feature = "compression")]
+        Compression::Zstd => Some(zstd::encode_all(data, 0)?),
+
+        _ => {
+            return Err(FroggiError::new(ErrorKind::CompressionError {
+                compression,
+            }))
+        }
+    };
+
+    Ok(compressed.filter(|compressed| compressed.len() < data.len()))
+}
+
+/// Decompress a page or item, refusing to decompress it past the limit for it.
+///
+/// `total` is how long the response is with everything decompressed so far. It grows by however
+/// much the data does, and decompressing stops once it's past the total length limit too.
+pub fn decompress(
+    compression: Compression,
+    data: Vec<u8>,
+    limit: Limit,
+    limits: &DecodeLimits,
+    total: &mut usize,
+) -> Result<Vec<u8>, FroggiError> {
+    if compression == Compression::None {
+        return Ok(data);
+    }
+
+    // one byte past either limit is enough to know it's too large
+    let room = limits
+        .max(Limit::Total)
+        .saturating_sub(*total)
+        .saturating_add(data.len());
+    let max = limits.max(limit).min(room);
+
+    let mut decompressed = Vec::new();
+    decoder(compression, &data)?
+        .take((max as u64).saturating_add(1))
+        .read_to_end(&mut decompressed)
+        .map_err(|error| {
+            FroggiError::new(ErrorKind::CompressionError { compression }).msg(error.to_string())
+        })?;
+    limits.check(limit, decompressed.len())?;
+
+    *total = total
+        .saturating_sub(data.len())
+        .saturating_add(decompressed.len());
+    limits.check(Limit::Total, *total)?;
+
+    Ok(decompressed)
+}
+
+fn decoder(compression: Compression, data: &[u8]) -> Result<Box<dyn Read + '_>, FroggiError> {
+    match compression {
+        Compression::None => Ok(Box::new(data)),
+
+        #[cfg(feature = "compression")]
+        Compression::Deflate => Ok(Box::new(flate2::read::DeflateDecoder::new(data))),
+
+        #[cfg(feature = "compression")]
+        Compression::Zstd => Ok(Box::new(zstd::Decoder::with_buffer(data)?)),
+
+        _ => Err(FroggiError::new(ErrorKind::CompressionError {
+            compression,
+        })),
+    }
+}
+
+#[cfg(all(test, feature = "compression"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let data = "(txt \"ribbit\")\n".repeat(100).into_bytes();
+
+        for &compression in Compression::supported() {
+            let compressed = compress(compression, &data).unwrap().unwrap();
+            assert!(compressed.len() < data.len());
+
+            let limits = DecodeLimits::default();
+            let decompressed = decompress(compression, compressed, Limit::Page, &limits, &mut 0);
+            assert_eq!(decompressed.unwrap(), data);
+        }
+
+        // data that doesn't shrink isn't compressed
+        assert!(compress(Compression::Zstd, b"frog").unwrap().is_none());
+        assert!(compress(Compression::Unknown(9), b"frog").is_err());
+    }
+
+    #[test]
+    fn limits() {
+        let data = vec![0; 4096];
+        let compressed = compress(Compression::Deflate, &data).unwrap().unwrap();
+
+        let limits = DecodeLimits {
+            max_item_size: 4095,
+            ..DecodeLimits::default()
+        };
+        assert!(matches!(
+            decompress(
+                Compression::Deflate,
+                compressed.clone(),
+                Limit::ItemSize,
+                &limits,
+                &mut 0
+            )
+            .unwrap_err()
+            .kind(),
+            ErrorKind::LimitError {
+                limit: Limit::ItemSize,
+                got: 4096,
+                max: 4095
+            }
+        ));
+
+        // and so does the response as a whole, counting what was decompressed before
+        let limits = DecodeLimits {
+            max_total: 4096 + 100,
+            ..DecodeLimits::default()
+        };
+        let mut total = 200 + compressed.len();
+        assert!(matches!(
+            decompress(
+                Compression::Deflate,
+                compressed.clone(),
+                Limit::ItemSize,
+                &limits,
+                &mut total
+            )
+            .unwrap_err()
+            .kind(),
+            ErrorKind::LimitError {
+                limit: Limit::Total,
+                got: 4197,
+                max: 4196
+            }
+        ));
+
+        let mut total = 100 + compressed.len();
+        decompress(
+            Compression::Deflate,
+            compressed,
+            Limit::ItemSize,
+            &limits,
+            &mut total,
+        )
+        .unwrap();
+        assert_eq!(total, 4196);
+
+        assert!(matches!(
+            decompress(
+                Compression::Zstd,
+                b"frog".to_vec(),
+                Limit::Page,
+                &limits,
+                &mut 0
+            )
+            .unwrap_err()
+            .kind(),
+            ErrorKind::CompressionError {
+                compression: Compression::Zstd
+            }
+        ));
+    }
+
+    #[test]
+    fn negotiate() {
+        use Compression::*;
+        assert_eq!(
+            Compression::negotiate(&[Unknown(7), Deflate, Zstd]),
+            Deflate
+        );
+        assert_eq!(Compression::negotiate(&[Unknown(7)]), None);
+        assert_eq!(Compression::negotiate(&[]), None);
+    }
+}
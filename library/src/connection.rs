@@ -0,0 +1,208 @@
+//! Sending several requests over one connection.
+//!
+//! Requests sent through a `Connection` ask the server to keep the connection open, so the next
+//! request doesn't have to wait for a new one. Servers close connections that have been idle for
+//! a while, and servers that don't keep connections open at all close them after responding.
+//! Either way the connection is opened again for the next request.
+//!
+//! Connections are opened with a `Transport`, so they can run over anything a `Client` can.
+
+use crate::request::{Request, RequestKind};
+use crate::response::Response;
+use crate::transport::{Tcp, Transport};
+use crate::{ErrorKind, FroggiError};
+
+use std::time::{Duration, Instant};
+
+/// How long a connection may sit idle before it's closed.
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A connection to a froggi server that is kept open between requests.
+#[derive(Debug)]
+pub struct Connection<T: Transport = Tcp> {
+    transport: T,
+    host: String,
+    port: u16,
+    stream: Option<T::Stream>,
+    last_used: Instant,
+    idle_timeout: Duration,
+}
+
+impl Connection {
+    /// Connect to a server over TCP.
+    pub fn open(host: &str, port: u16) -> Result<Connection, FroggiError> {
+        Connection::open_with(Tcp::new(), host, port)
+    }
+}
+
+impl<T: Transport> Connection<T> {
+    /// Connect to a server over some other transport.
+    pub fn open_with(transport: T, host: &str, port: u16) -> Result<Connection<T>, FroggiError> {
+        let stream = transport.connect(host, port)?;
+
+        Ok(Connection {
+            transport,
+            host: host.into(),
+            port,
+            stream: Some(stream),
+            last_used: Instant::now(),
+            idle_timeout: IDLE_TIMEOUT,
+        })
+    }
+
+    /// Open a new connection instead of reusing one that has been idle for this long.
+    ///
+    /// This should be no longer than the server's idle timeout.
+    pub fn with_idle_timeout(self, idle_timeout: Duration) -> Connection<T> {
+        Connection {
+            idle_timeout,
+            ..self
+        }
+    }
+
+    /// Send a request and return its response, leaving the connection open afterwards.
+    ///
+    /// If a reused connection turns out to have been closed by the server, the request is sent
+    /// again on a new connection, unless it's a put request. Error responses from the server are
+    /// returned as `ErrorKind::StatusError`.
+    pub fn send(&mut self, request: Request) -> Result<Response, FroggiError> {
+        let request = request.with_keep_alive();
+
+        if self.last_used.elapsed() > self.idle_timeout {
+            self.stream = None;
+        }
+
+        let reused = self.stream.is_some();
+        let response = match self.exchange(&request) {
+            Err(error) if reused && is_closed(&error) && !is_put(&request) => {
+                self.exchange(&request)
+            }
+            result => result,
+        }?;
+
+        self.last_used = Instant::now();
+        response.error_for_status()
+    }
+
+    /// True if the connection is currently open.
+    ///
+    /// The server may have closed it without us noticing yet.
+    pub fn is_open(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    // send the request on the open connection, opening a new one if there isn't one
+    fn exchange(&mut self, request: &Request) -> Result<Response, FroggiError> {
+        let mut stream = match self.stream.take() {
+            Some(stream) => stream,
+            None => self.transport.connect(&self.host, self.port)?,
+        };
+
+        // if anything goes wrong, we don't know where the next response starts, so the
+        // connection is dropped
+        request.write_to(&mut stream)?;
+        let response = Response::from_bytes(&mut stream)?;
+
+        self.stream = Some(stream);
+        Ok(response)
+    }
+}
+
+// whether an error looks like the connection was closed out from under us
+fn is_closed(error: &FroggiError) -> bool {
+    matches!(error.kind(), ErrorKind::IOError { .. })
+}
+
+fn is_put(request: &Request) -> bool {
+    matches!(request.kind(), RequestKind::Put)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::response::{Item, ItemKind, ResponseBuilder};
+    use crate::transport::{memory, Listener};
+
+    use std::net::TcpListener;
+    use std::thread;
+
+    // answer requests with their path until the client stops keeping the connection alive,
+    // counting how many connections were made
+    fn echo_server(
+        listener: impl Listener + Send + 'static,
+        connections: usize,
+    ) -> thread::JoinHandle<usize> {
+        thread::spawn(move || {
+            let mut accepted = 0;
+            while accepted < connections {
+                let mut stream = listener.accept().unwrap();
+                accepted += 1;
+
+                while let Ok(request) = Request::from_bytes(&mut stream) {
+                    let path = request.request().as_bytes();
+                    // with an item, whose end has to be found without waiting on the open connection
+                    ResponseBuilder::default()
+                        .page(request.request().into())
+                        .item(Item::new("path".into(), ItemKind::Unknown(15), path.into()))
+                        .build()
+                        .unwrap()
+                        .write_to(&mut stream)
+                        .unwrap();
+
+                    if !request.keep_alive() {
+                        break;
+                    }
+                }
+            }
+            accepted
+        })
+    }
+
+    fn page(path: &str) -> Request {
+        Request::new(path, RequestKind::PageOnly).unwrap()
+    }
+
+    fn send_three(mut connection: Connection<impl Transport>) {
+        assert_eq!(connection.send(page("a.fml")).unwrap().page(), "a.fml");
+        assert_eq!(connection.send(page("b.fml")).unwrap().page(), "b.fml");
+        assert_eq!(connection.send(page("c.fml")).unwrap().page(), "c.fml");
+    }
+
+    #[test]
+    fn keep_alive() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = echo_server(listener, 1);
+
+        send_three(Connection::open(&addr.ip().to_string(), addr.port()).unwrap());
+        assert_eq!(server.join().unwrap(), 1);
+    }
+
+    #[test]
+    fn keep_alive_in_memory() {
+        let (transport, listener) = memory();
+        let server = echo_server(listener, 1);
+
+        send_three(Connection::open_with(transport, "anywhere", 0).unwrap());
+        assert_eq!(server.join().unwrap(), 1);
+    }
+
+    #[test]
+    fn idle_timeout() {
+        let (transport, listener) = memory();
+        let server = echo_server(listener, 3);
+
+        // every request is sent on a new connection
+        let mut connection = Connection::open_with(transport, "anywhere", 0)
+            .unwrap()
+            .with_idle_timeout(Duration::from_secs(0));
+        thread::sleep(Duration::from_millis(10));
+        assert_eq!(connection.send(page("a.fml")).unwrap().page(), "a.fml");
+        thread::sleep(Duration::from_millis(10));
+        assert_eq!(connection.send(page("b.fml")).unwrap().page(), "b.fml");
+        drop(connection);
+
+        // the first connection was never used
+        assert_eq!(server.join().unwrap(), 3);
+    }
+}
@@ -0,0 +1,84 @@
+//! Optional key/value fields at the end of a request.
+//!
+//! Each extension is a one byte key, a two byte length, and a value. Servers skip the keys they
+//! don't know about, so new keys can be added without breaking anyone.
+
+use crate::{AddMsg, ErrorKind, FroggiError};
+use froggi_derive::U8Enum;
+
+/// Extension keys that have a meaning.
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, U8Enum)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ExtensionKey {
+    /// The name and version of the client, as text.
+    UserAgent = 0,
+    /// The languages the client would like pages in, as text, like "en-US, fr".
+    Language = 1,
+    /// The item kinds the client can display, one byte each.
+    AcceptItems = 2,
+    /// The width and height of the client's screen in pixels, two bytes each.
+    ScreenSize = 3,
+    /// The compression codecs the client can decompress, one byte each, most preferred first.
+    AcceptCompression = 4,
+    /// A key we don't know about.
+    Unknown(u8),
+}
+
+/// A single key/value field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "ExtensionFields"))]
+pub struct Extension {
+    key: u8,
+    #[cfg_attr(feature = "serde", serde(with = "crate::compact"))]
+    value: Vec<u8>,
+}
+
+// deserialized extensions are checked like any other
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct ExtensionFields {
+    key: u8,
+    #[serde(with = "crate::compact")]
+    value: Vec<u8>,
+}
+
+#[cfg(feature = "serde")]
+impl std::convert::TryFrom<ExtensionFields> for Extension {
+    type Error = FroggiError;
+
+    fn try_from(fields: ExtensionFields) -> Result<Extension, FroggiError> {
+        Extension::new(fields.key, fields.value)
+    }
+}
+
+impl Extension {
+    /// Create a new extension. The value can be at most `u16::MAX` bytes long.
+    pub fn new(key: impl Into<u8>, value: Vec<u8>) -> Result<Extension, FroggiError> {
+        if value.len() > u16::MAX as usize {
+            return Err(FroggiError::new(ErrorKind::RequestFormatError)
+                .msg_str("The extension value is too large."));
+        }
+
+        Ok(Extension {
+            key: key.into(),
+            value,
+        })
+    }
+
+    /// Get the key of the extension, even if we don't know what it means
+    pub fn key(&self) -> u8 {
+        self.key
+    }
+
+    /// Get the key of the extension as one we know about
+    pub fn known_key(&self) -> ExtensionKey {
+        self.key.into()
+    }
+
+    /// Get the value of the extension
+    pub fn value(&self) -> &[u8] {
+        &self.value
+    }
+}
@@ -1,12 +1,20 @@
 //! Froggi protocol library
 //!
 //! Add `features = ['markup']` to get markup AST and parsing, and for more layout-oriented types.
+//!
+//! Add `features = ['async']` to read and write requests and responses with tokio's `AsyncRead`
+//! and `AsyncWrite`.
+//!
+//! Add `features = ['tls']` to talk to servers over TLS using rustls.
+//!
+//! Add `features = ['serde']` to serialize requests, responses and documents with serde.
 
 use std::fmt;
-use std::io::{self, Write};
+use std::io;
 use std::net::{TcpStream, ToSocketAddrs};
 use std::str;
 
+pub use client::Client;
 pub use uuid::Uuid;
 
 #[cfg(feature = "ui")]
@@ -15,26 +23,49 @@ pub mod ui;
 #[cfg(feature = "markup")]
 pub mod markup;
 
+pub mod client;
+pub mod codec;
+pub mod compression;
+pub mod connection;
+pub mod extension;
+pub mod hash;
+pub mod limits;
+pub mod media;
 pub mod protocol;
+pub mod redirect;
 pub mod request;
 pub mod response;
+pub mod store;
+pub mod transport;
+pub mod url;
+
+#[cfg(feature = "tls")]
+pub mod tls;
+
+#[cfg(feature = "serde")]
+mod compact;
 
 use markup::scan::TokenKind;
 use request::RequestKind;
 
-/// Froggi version.
-pub const FROGGI_VERSION: u8 = 0;
+/// The newest froggi version, which responses are written in unless told otherwise.
+///
+/// Requests are written in the oldest version they fit in unless told otherwise, and `Client`s
+/// fall back to the oldest version when a server doesn't understand a newer one.
+pub const FROGGI_VERSION: u8 = 1;
+
+/// Every froggi version this library can read and write, oldest first.
+pub const FROGGI_VERSIONS: [u8; 2] = [0, 1];
 
 /// Send a froggi request to a server and return its response.
+///
+/// Error responses from the server are returned as `ErrorKind::StatusError`.
 pub fn send_request(
     to: impl ToSocketAddrs,
     request: &str,
     kind: RequestKind,
 ) -> Result<response::Response, FroggiError> {
-    let mut stream = TcpStream::connect(to)?;
-    stream.write_all(&request::Request::new_with_id(request, Uuid::nil(), kind)?.bytes())?;
-
-    Ok(response::Response::from_bytes(&mut stream)?)
+    send_request_with_id(to, request, Uuid::nil(), kind)
 }
 
 /// Send a froggi request to a server with a client ID and return its response.
@@ -44,10 +75,151 @@ pub fn send_request_with_id(
     id: Uuid,
     kind: RequestKind,
 ) -> Result<response::Response, FroggiError> {
-    let mut stream = TcpStream::connect(to)?;
-    stream.write_all(&request::Request::new_with_id(request, id, kind)?.bytes())?;
+    send(to, &request::Request::new_with_id(request, id, kind)?)
+}
+
+/// Send a request that's already been built to a server and return its response.
+///
+/// Error responses from the server are returned as `ErrorKind::StatusError`.
+pub fn send(
+    to: impl ToSocketAddrs,
+    request: &request::Request,
+) -> Result<response::Response, FroggiError> {
+    send_on(TcpStream::connect(to)?, request)
+}
+
+/// Send a request over a connection that's already open and return its response.
+///
+/// The connection can be anything, see the `transport` module for some options. Error responses
+/// from the server are returned as `ErrorKind::StatusError`.
+pub fn send_on(
+    mut stream: impl io::Read + io::Write,
+    request: &request::Request,
+) -> Result<response::Response, FroggiError> {
+    request.write_to(&mut stream)?;
+    stream.flush()?;
+
+    response::Response::from_bytes(&mut stream)?.error_for_status()
+}
+
+/// Send a request, letting the server leave out items that are already in a local store.
+///
+/// Only the items the page referenced the last time it came from this server are listed in the
+/// request. The items in the response are put back together from the store, and new items are
+/// added to it.
+pub fn send_with_store(
+    to: impl ToSocketAddrs,
+    request: request::Request,
+    store: &mut impl store::ItemStore,
+) -> Result<response::Response, FroggiError> {
+    let stream = TcpStream::connect(to)?;
+    let page = format!("{}/{}", stream.peer_addr()?, request.request());
+
+    let request = request.with_known_items(store.page_hashes(&page))?;
+    let mut response = send_on(stream, &request)?;
+
+    response.remember_items(&page, store);
+    response.fill_items(store)?;
+
+    Ok(response)
+}
+
+/// Send some data to a server in a put request and return its response.
+pub fn send_put(
+    to: impl ToSocketAddrs,
+    request: &str,
+    id: Uuid,
+    body_kind: response::ItemKind,
+    body: Vec<u8>,
+) -> Result<response::Response, FroggiError> {
+    send(
+        to,
+        &request::Request::new_put(request, id, body_kind, body)?,
+    )
+}
+
+/// Send a froggi request for a URL and return its response.
+///
+/// Only `frgi://` URLs are supported, see `tls::send_request_to_url` for `frgis://`.
+pub fn send_request_to_url(
+    url: &url::Url,
+    kind: RequestKind,
+) -> Result<response::Response, FroggiError> {
+    send_request_to_url_with_id(url, Uuid::nil(), kind)
+}
+
+/// Send a froggi request for a URL with a client ID and return its response.
+pub fn send_request_to_url_with_id(
+    url: &url::Url,
+    id: Uuid,
+    kind: RequestKind,
+) -> Result<response::Response, FroggiError> {
+    if url.scheme() != url::Scheme::Frgi {
+        return Err(FroggiError::new(ErrorKind::UrlError {
+            error: UrlError::TlsRequired {
+                url: url.to_string(),
+            },
+        }));
+    }
+
+    send_request_with_id(url.socket_addr(), url.request(), id, kind)
+}
+
+/// Send a froggi request for a URL with a client ID, following redirects.
+///
+/// Returns the URL the final response came from, which relative links should be resolved
+/// against, along with the response.
+pub fn send_request_following_redirects(
+    url: &url::Url,
+    id: Uuid,
+    kind: RequestKind,
+    policy: redirect::RedirectPolicy,
+) -> Result<(url::Url, response::Response), FroggiError> {
+    redirect::follow(url, id, policy, |url, id| {
+        send_request_to_url_with_id(url, id, kind)
+    })
+}
+
+/// Send a froggi request to a server asynchronously and return its response.
+#[cfg(feature = "async")]
+pub async fn send_request_async(
+    to: impl tokio::net::ToSocketAddrs,
+    request: &str,
+    kind: RequestKind,
+) -> Result<response::Response, FroggiError> {
+    send_request_with_id_async(to, request, Uuid::nil(), kind).await
+}
+
+/// Send a froggi request to a server asynchronously with a client ID and return its response.
+#[cfg(feature = "async")]
+pub async fn send_request_with_id_async(
+    to: impl tokio::net::ToSocketAddrs,
+    request: &str,
+    id: Uuid,
+    kind: RequestKind,
+) -> Result<response::Response, FroggiError> {
+    let mut stream = tokio::net::TcpStream::connect(to).await?;
+    request::Request::new_with_id(request, id, kind)?
+        .write_to_async(&mut stream)
+        .await?;
+
+    response::Response::from_bytes_async(&mut stream)
+        .await?
+        .error_for_status()
+}
 
-    Ok(response::Response::from_bytes(&mut stream)?)
+/// Check the magic and version at the start of a request or response header, and get the codec
+/// for the rest of it.
+pub(crate) fn check_header(header: &[u8]) -> Result<codec::Codec, FroggiError> {
+    use protocol::*;
+
+    if header[FROGGI_MAGIC_OFFSET..FROGGI_VERSION_OFFSET] != FROGGI_MAGIC {
+        let mut got = [0u8; FROGGI_MAGIC_LEN];
+        got.copy_from_slice(&header[FROGGI_MAGIC_OFFSET..FROGGI_VERSION_OFFSET]);
+        return Err(FroggiError::new(ErrorKind::MagicError { got }));
+    }
+
+    codec::Codec::for_version(header[FROGGI_VERSION_OFFSET])
 }
 
 /// Serialize a usize into a little-endian pair of bytes.
@@ -182,6 +354,19 @@ impl std::error::Error for FroggiError {
             ErrorKind::IOError { error } => error.source(),
             ErrorKind::ScanError { .. } => None,
             ErrorKind::ParseError { .. } => None,
+            ErrorKind::UrlError { .. } => None,
+            ErrorKind::RedirectError { .. } => None,
+            ErrorKind::AnchorError { .. } => None,
+            ErrorKind::StatusError { .. } => None,
+            ErrorKind::MagicError { .. } => None,
+            ErrorKind::VersionError { .. } => None,
+            ErrorKind::VarintError { .. } => None,
+            ErrorKind::LengthMismatchError { .. } => None,
+            ErrorKind::LimitError { .. } => None,
+            ErrorKind::MissingItemError { .. } => None,
+            ErrorKind::CompressionError { .. } => None,
+            #[cfg(feature = "tls")]
+            ErrorKind::TlsError { error } => error.source(),
         }
     }
 }
@@ -244,6 +429,75 @@ pub enum ErrorKind {
         /// Where it happened
         line: usize,
     },
+    /// Couldn't make sense of a URL
+    UrlError {
+        /// The URL error
+        error: UrlError,
+    },
+    /// Refused to follow a redirect
+    RedirectError {
+        /// The redirect error
+        error: RedirectError,
+    },
+    /// The data didn't start with the froggi magic
+    MagicError {
+        /// What it started with instead
+        got: [u8; 4],
+    },
+    /// The peer speaks a version of froggi we don't
+    VersionError {
+        /// The version we got
+        got: u8,
+    },
+    /// A length wasn't a valid varint
+    VarintError {
+        /// How many bytes were read before it went wrong
+        bytes: usize,
+    },
+    /// The total response length didn't match the length of its contents
+    LengthMismatchError {
+        /// Length according to the header
+        expected: usize,
+        /// Length of the contents
+        got: usize,
+    },
+    /// Something was larger than we're willing to read
+    LimitError {
+        /// The limit that was exceeded
+        limit: limits::Limit,
+        /// The length we got
+        got: usize,
+        /// The most we'd allow
+        max: usize,
+    },
+    /// The server responded with an error
+    StatusError {
+        /// Why the server says it's an error
+        code: response::ErrorCode,
+    },
+    /// The document has no anchor to insert expressions at
+    AnchorError {
+        /// Name of the anchor
+        anchor: String,
+    },
+    /// An item the server left out isn't in the local store
+    MissingItemError {
+        /// Name of the item
+        name: String,
+        /// Hash of the item's data
+        hash: hash::ContentHash,
+    },
+    /// Part of a response couldn't be compressed or decompressed
+    CompressionError {
+        /// The codec it was compressed with
+        compression: compression::Compression,
+    },
+    /// Couldn't set up or maintain a TLS session
+    #[cfg(feature = "tls")]
+    TlsError {
+        /// The rustls error
+        error: rustls::Error,
+    },
 }
 
 #[rustfmt::skip]
@@ -266,6 +520,31 @@ impl fmt::Display for ErrorKind {
                 => write!(f, "scan error on line {} - {}", line, error),
             ErrorKind::ParseError { error, line }
                 => write!(f, "parse error on line {} - {}", line, error),
+            ErrorKind::UrlError { error }
+                => write!(f, "url error - {}", error),
+            ErrorKind::RedirectError { error }
+                => write!(f, "redirect error - {}", error),
+            ErrorKind::MagicError { got }
+                => write!(f, "magic error - expected froggi magic, got {:02x?}", got),
+            ErrorKind::VersionError { got }
+                => write!(f, "version error - unsupported froggi version {}", got),
+            ErrorKind::VarintError { bytes }
+                => write!(f, "varint error - invalid or overlong length after {} bytes", bytes),
+            ErrorKind::LengthMismatchError { expected, got }
+                => write!(f, "length mismatch - header says {} bytes, contents are {}", expected, got),
+            ErrorKind::LimitError { limit, got, max }
+                => write!(f, "limit error - {} {} is larger than the limit of {}", limit, got, max),
+            ErrorKind::StatusError { code }
+                => write!(f, "status error - server responded with {:?}", code),
+            ErrorKind::AnchorError { anchor }
+                => write!(f, "anchor error - no anchor named {:?}", anchor),
+            ErrorKind::MissingItemError { name, hash }
+                => write!(f, "missing item error - {} ({}) was left out and isn't stored locally", name, hash),
+            ErrorKind::CompressionError { compression }
+                => write!(f, "compression error - couldn't use {:?} compression", compression),
+            #[cfg(feature = "tls")]
+            ErrorKind::TlsError { error }
+                => write!(f, "tls error - {}", error),
         }
     }
 }
@@ -359,6 +638,90 @@ impl fmt::Display for ParseError {
     }
 }
 
+/// Froggi URL error.
+#[derive(Debug)]
+pub enum UrlError {
+    /// The URL has no scheme, so it isn't absolute
+    NotAbsolute {
+        /// The URL
+        url: String,
+    },
+    /// The scheme is not frgi or frgis
+    UnknownScheme {
+        /// The scheme that was found
+        scheme: String,
+    },
+    /// The URL has no host
+    MissingHost {
+        /// The URL
+        url: String,
+    },
+    /// The port is not a number between 0 and 65535
+    InvalidPort {
+        /// The port that was found
+        port: String,
+    },
+    /// The URL is frgis, but TLS wasn't used to request it
+    TlsRequired {
+        /// The URL
+        url: String,
+    },
+}
+
+#[rustfmt::skip]
+impl fmt::Display for UrlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UrlError::NotAbsolute { url }
+            => write!(f, "expected an absolute url, got {:?}", url),
+            UrlError::UnknownScheme { scheme }
+            => write!(f, "unknown scheme {:?}", scheme),
+            UrlError::MissingHost { url }
+            => write!(f, "no host in {:?}", url),
+            UrlError::InvalidPort { port }
+            => write!(f, "invalid port {:?}", port),
+            UrlError::TlsRequired { url }
+            => write!(f, "{} must be requested over TLS", url),
+        }
+    }
+}
+
+/// Froggi redirect error.
+#[derive(Debug)]
+pub enum RedirectError {
+    /// Followed as many redirects as the policy allows
+    TooManyRedirects {
+        /// The most redirects the policy allows
+        max: usize,
+    },
+    /// The redirect leads back to a URL that was already requested
+    Loop {
+        /// The URL
+        url: String,
+    },
+    /// The redirect leads to a different scheme, host or port, and the policy doesn't allow that
+    CrossHost {
+        /// The URL that redirected
+        from: String,
+        /// Where it redirected to
+        to: String,
+    },
+}
+
+#[rustfmt::skip]
+impl fmt::Display for RedirectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RedirectError::TooManyRedirects { max }
+            => write!(f, "more than {} redirects", max),
+            RedirectError::Loop { url }
+            => write!(f, "redirect loop at {}", url),
+            RedirectError::CrossHost { from, to }
+            => write!(f, "{} redirects to a different server, {}", from, to),
+        }
+    }
+}
+
 trait AddMsg {
     fn msg(self, msg: String) -> Self;
     fn msg_str(self, msg: &str) -> Self;
@@ -406,33 +769,11 @@ impl From<io::Error> for FroggiError {
     }
 }
 
-/// Create a u8-based enum with From and Into impls.
-#[macro_export]
-macro_rules! u8enum {
-    ($name:ident { $($variant:ident = $value:expr),* $(,)? }) => {
-        #[repr(u8)]
-        #[derive(Copy, Clone, Debug)]
-        pub enum $name {
-            $($variant = $value,)*
-        }
-
-        impl Into<u8> for $name {
-            fn into(self) -> u8 {
-                match self {
-                    $($name::$variant => $value,)*
-                }
-            }
-        }
-
-        impl From<u8> for $name {
-            fn from(b: u8) -> $name {
-                match b {
-                    $($value => $name::$variant,)*
-                    _ => $name::Unknown,
-                }
-            }
-        }
-    };
+#[cfg(feature = "tls")]
+impl From<rustls::Error> for FroggiError {
+    fn from(error: rustls::Error) -> FroggiError {
+        FroggiError::new(ErrorKind::TlsError { error })
+    }
 }
 
 #[cfg(test)]
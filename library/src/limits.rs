@@ -0,0 +1,135 @@
+//! Limits on what we're willing to read from a peer.
+//!
+//! Lengths on the wire are chosen by whoever is on the other end of the connection, so they're
+//! checked against these limits before anything is allocated for them.
+
+use crate::{ErrorKind, FroggiError};
+
+use std::fmt;
+
+/// Which limit was exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// The length of a response's page
+    Page,
+    /// The number of items in a response
+    Items,
+    /// The length of a single item
+    ItemSize,
+    /// The total length of a response
+    Total,
+    /// The length of a put request's body
+    Body,
+    /// The length of a request's path
+    Path,
+    /// The number of known item hashes in a request
+    KnownItems,
+    /// The length of all of a request's extension values together
+    Extensions,
+    /// The length of an item's name
+    ItemName,
+    /// The length of an item's media type
+    MediaType,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Limit::Page => write!(f, "page length"),
+            Limit::Items => write!(f, "number of items"),
+            Limit::ItemSize => write!(f, "item length"),
+            Limit::Total => write!(f, "total response length"),
+            Limit::Body => write!(f, "request body length"),
+            Limit::Path => write!(f, "request path length"),
+            Limit::KnownItems => write!(f, "number of known items"),
+            Limit::Extensions => write!(f, "request extensions length"),
+            Limit::ItemName => write!(f, "item name length"),
+            Limit::MediaType => write!(f, "item media type length"),
+        }
+    }
+}
+
+/// The largest things we'll decode. Lengths are in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeLimits {
+    /// Longest page in a response
+    pub max_page: usize,
+    /// Most items in a response
+    pub max_items: usize,
+    /// Longest single item in a response
+    pub max_item_size: usize,
+    /// Longest response, including the header
+    pub max_total: usize,
+    /// Longest body of a put request
+    pub max_body: usize,
+    /// Longest path in a request
+    pub max_path: usize,
+    /// Most known item hashes in a request
+    pub max_known_items: usize,
+    /// Longest extension values in a request, all added together
+    pub max_extensions: usize,
+    /// Longest item name, in a response or an item request
+    pub max_item_name: usize,
+    /// Longest media type of an item in a response
+    pub max_media_type: usize,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        DecodeLimits {
+            max_page: 16 * 1024 * 1024,
+            max_items: 1024,
+            max_item_size: 64 * 1024 * 1024,
+            max_total: 128 * 1024 * 1024,
+            max_body: 16 * 1024 * 1024,
+            max_path: 1024 * 1024,
+            max_known_items: 1024,
+            max_extensions: 64 * 1024,
+            max_item_name: 64 * 1024,
+            max_media_type: 255,
+        }
+    }
+}
+
+impl DecodeLimits {
+    /// No limits beyond what the protocol can express.
+    pub fn unlimited() -> Self {
+        DecodeLimits {
+            max_page: usize::MAX,
+            max_items: usize::MAX,
+            max_item_size: usize::MAX,
+            max_total: usize::MAX,
+            max_body: usize::MAX,
+            max_path: usize::MAX,
+            max_known_items: usize::MAX,
+            max_extensions: usize::MAX,
+            max_item_name: usize::MAX,
+            max_media_type: usize::MAX,
+        }
+    }
+
+    /// Get the largest length allowed for something
+    pub fn max(&self, limit: Limit) -> usize {
+        match limit {
+            Limit::Page => self.max_page,
+            Limit::Items => self.max_items,
+            Limit::ItemSize => self.max_item_size,
+            Limit::Total => self.max_total,
+            Limit::Body => self.max_body,
+            Limit::Path => self.max_path,
+            Limit::KnownItems => self.max_known_items,
+            Limit::Extensions => self.max_extensions,
+            Limit::ItemName => self.max_item_name,
+            Limit::MediaType => self.max_media_type,
+        }
+    }
+
+    pub(crate) fn check(&self, limit: Limit, got: usize) -> Result<(), FroggiError> {
+        let max = self.max(limit);
+        if got > max {
+            Err(FroggiError::new(ErrorKind::LimitError { limit, got, max }))
+        } else {
+            Ok(())
+        }
+    }
+}
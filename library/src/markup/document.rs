@@ -1,11 +1,13 @@
 //! Page types that are easier to deal with than the raw AST.
 
 use crate::markup::{ExpressionPayload, InlineStyle, Page, PageExpression, PageStyles};
+use crate::{AddMsg, ErrorKind, FroggiError};
 
 use std::collections::HashMap;
 
 /// An owned document. More useful than Page for drawing to the screen.
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Document {
     styles: HashMap<String, Style>,
     expressions: Vec<DocumentExpression>,
@@ -43,6 +45,110 @@ impl Document {
     pub fn expressions(&self) -> &[DocumentExpression] {
         &self.expressions
     }
+
+    /// Insert expressions directly after an anchor, which may be nested in other expressions.
+    ///
+    /// The anchor is kept, so later insertions at the same anchor go before these ones.
+    pub fn insert_at_anchor(
+        &mut self,
+        anchor: &str,
+        expressions: Vec<DocumentExpression>,
+    ) -> Result<(), FroggiError> {
+        let mut expressions = Some(expressions);
+        if insert_after_anchor(&mut self.expressions, anchor, &mut expressions) {
+            Ok(())
+        } else {
+            Err(FroggiError::new(ErrorKind::AnchorError {
+                anchor: anchor.into(),
+            }))
+        }
+    }
+
+    /// Merge the page of an additional page expressions response into the document.
+    ///
+    /// Each `(# "anchor")` at the top level of the page starts a group of expressions which are
+    /// inserted at the anchor of the same name in the document. Styles defined by the page are
+    /// added to the document. Nothing is inserted unless every anchor exists.
+    pub fn insert_additional(&mut self, page: &Page) -> Result<(), FroggiError> {
+        let mut groups: Vec<(String, Vec<DocumentExpression>)> = Vec::new();
+
+        for expression in page.expressions.iter() {
+            match (&expression.payload, groups.last_mut()) {
+                (ExpressionPayload::Anchor { anchor }, _) => {
+                    groups.push((anchor.clone_lexeme(), Vec::new()))
+                }
+
+                (_, Some((_, group))) => group.push(DocumentExpression::from_page_expression(
+                    expression,
+                    &page.styles,
+                )),
+
+                (_, None) => {
+                    return Err(FroggiError::new(ErrorKind::ResponseFormatError)
+                        .msg_str("additional page expressions must start with an anchor"))
+                }
+            }
+        }
+
+        if let Some((anchor, _)) = groups
+            .iter()
+            .find(|(anchor, _)| !has_anchor(&self.expressions, anchor))
+        {
+            return Err(FroggiError::new(ErrorKind::AnchorError {
+                anchor: anchor.clone(),
+            }));
+        }
+
+        for (name, styles) in page.styles.iter() {
+            let mut style = Style::new();
+            inline_styles_to_style(styles, &HashMap::with_capacity(0), &mut style);
+            self.styles.insert(name.clone_lexeme(), style);
+        }
+
+        for (anchor, expressions) in groups {
+            self.insert_at_anchor(&anchor, expressions)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn is_anchor(expression: &DocumentExpression, anchor: &str) -> bool {
+    matches!(&expression.contents, DocumentExpressionContents::Anchor { name } if name == anchor)
+}
+
+fn has_anchor(expressions: &[DocumentExpression], anchor: &str) -> bool {
+    expressions.iter().any(|expression| {
+        is_anchor(expression, anchor)
+            || match &expression.contents {
+                DocumentExpressionContents::Children { children } => has_anchor(children, anchor),
+                _ => false,
+            }
+    })
+}
+
+// returns true if the anchor was found and the expressions were inserted
+fn insert_after_anchor(
+    expressions: &mut Vec<DocumentExpression>,
+    anchor: &str,
+    insert: &mut Option<Vec<DocumentExpression>>,
+) -> bool {
+    for i in 0..expressions.len() {
+        if is_anchor(&expressions[i], anchor) {
+            // unwrap safety - we only take it once, and return right after
+            let insert = insert.take().unwrap();
+            expressions.splice(i + 1..i + 1, insert);
+            return true;
+        }
+
+        if let DocumentExpressionContents::Children { children } = &mut expressions[i].contents {
+            if insert_after_anchor(children, anchor, insert) {
+                return true;
+            }
+        }
+    }
+
+    false
 }
 
 impl ToString for Document {
@@ -67,6 +173,7 @@ impl ToString for Document {
 
 /// An owned document expression.
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DocumentExpression {
     style: Style,
     direction: Direction,
@@ -144,6 +251,7 @@ impl DocumentExpression {
 
 /// Contents of a document expression. Stored independently of its layout.
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DocumentExpressionContents {
     /// Plain text
     Text {
@@ -217,6 +325,7 @@ impl DocumentExpressionContents {
 
 /// Direction for screen layout.
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Direction {
     /// Items are laid out horizontally
     Horizontal,
@@ -230,6 +339,7 @@ pub enum Direction {
 ///
 /// Created separately from page parsing.
 #[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FontType {
     /// Monospace font
     Mono,
@@ -241,6 +351,7 @@ pub enum FontType {
 
 /// Style of a font.
 #[derive(Debug, PartialEq, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FontStyle {
     /// Bold-face font
     pub bold: bool,
@@ -254,6 +365,7 @@ pub struct FontStyle {
 
 /// The style of an expression.
 #[derive(Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Style {
     /// The type of font
     pub font_type: FontType,
@@ -368,6 +480,18 @@ mod test {
         );
     }
 
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let page =
+            r#"{(a italic)} (wide ({a} "frog") (^ "pond.fml" "pond") (& "frog.png" "a frog"))"#;
+        let page = crate::markup::parse::parse(page).unwrap();
+        let document = Document::from_page(&page);
+
+        let json = serde_json::to_string(&document).unwrap();
+        assert_eq!(serde_json::from_str::<Document>(&json).unwrap(), document);
+    }
+
     #[test]
     fn bg_fg_application_order() {
         let page = r#"
@@ -415,6 +539,87 @@ mod test {
         );
     }
 
+    fn text(text: &str) -> DocumentExpression {
+        DocumentExpression {
+            style: Style::new(),
+            direction: Direction::Vertical,
+            contents: DocumentExpressionContents::Text { text: text.into() },
+        }
+    }
+
+    #[test]
+    fn insert_at_anchor() {
+        let page = r#"("title") (wide ("left") (# "comments")) (# "footer")"#;
+        let mut document = Document::from_page(&crate::markup::parse::parse(page).unwrap());
+
+        document
+            .insert_at_anchor("comments", vec![text("second")])
+            .unwrap();
+        document
+            .insert_at_anchor("comments", vec![text("first")])
+            .unwrap();
+        document
+            .insert_at_anchor("footer", vec![text("bye")])
+            .unwrap();
+
+        let expected =
+            r#"("title") (wide ("left") (# "comments") ("first") ("second")) (# "footer") ("bye")"#;
+        let expected = Document::from_page(&crate::markup::parse::parse(expected).unwrap());
+        assert_eq!(document, expected);
+
+        assert!(matches!(
+            document.insert_at_anchor("header", vec![text("hi")]).unwrap_err().kind(),
+            ErrorKind::AnchorError { anchor } if anchor == "header"
+        ));
+    }
+
+    #[test]
+    fn insert_additional() {
+        let page = r#"("title") (# "comments") (# "footer")"#;
+        let mut document = Document::from_page(&crate::markup::parse::parse(page).unwrap());
+
+        let additional = r#"
+        {(quiet italic)}
+        (# "comments")
+        ({quiet} "first!")
+        ("second")
+        (# "footer")
+        ("updated just now")
+        "#;
+        document
+            .insert_additional(&crate::markup::parse::parse(additional).unwrap())
+            .unwrap();
+
+        assert!(document.styles().contains_key("quiet"));
+        let texts = document
+            .expressions()
+            .iter()
+            .filter_map(|expression| match expression.contents() {
+                DocumentExpressionContents::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(texts, vec!["title", "first!", "second", "updated just now"]);
+        assert!(document.expressions()[2].style().font_style.italic);
+
+        // nothing is inserted if an anchor is missing
+        let before = document.expressions().len();
+        let additional = r#"(# "footer") ("a") (# "header") ("b")"#;
+        assert!(document
+            .insert_additional(&crate::markup::parse::parse(additional).unwrap())
+            .is_err());
+        assert_eq!(document.expressions().len(), before);
+
+        let additional = r#"("no anchor")"#;
+        assert!(matches!(
+            document
+                .insert_additional(&crate::markup::parse::parse(additional).unwrap())
+                .unwrap_err()
+                .kind(),
+            ErrorKind::ResponseFormatError
+        ));
+    }
+
     #[test]
     fn new_test_markup() {
         let train_doc = Document::from_page(
@@ -12,7 +12,7 @@ use scan::{Token, TokenKind};
 use std::collections::HashMap;
 
 /// A no-copy page.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Page<'a> {
     /// Page-level styles
     pub styles: PageStyles<'a>,
@@ -20,7 +20,7 @@ pub struct Page<'a> {
     pub expressions: Vec<PageExpression<'a>>,
 }
 
-impl Page<'_> {
+impl<'a> Page<'a> {
     pub fn item_names(&self) -> Vec<String> {
         let mut names = Vec::new();
         for expression in &self.expressions {
@@ -28,13 +28,57 @@ impl Page<'_> {
         }
         names
     }
+
+    /// Get the names of the page's anchors, including ones nested in other expressions.
+    pub fn anchors(&self) -> Vec<String> {
+        let mut anchors = Vec::new();
+        for expression in &self.expressions {
+            expression.anchors(&mut anchors);
+        }
+        anchors
+    }
+
+    /// Get the part of the page at an anchor, or `None` if the page has no such anchor.
+    ///
+    /// An anchor at the top level of the page starts a fragment that runs up to the next
+    /// top-level anchor. A nested anchor's fragment is the innermost expression it's in. The
+    /// fragment keeps the page styles, and its `item_names` are only those of the items it
+    /// references.
+    pub fn fragment(&self, anchor: &str) -> Option<Page<'a>> {
+        let expressions = match self
+            .expressions
+            .iter()
+            .position(|expression| expression.is_anchor(anchor))
+        {
+            Some(start) => {
+                let end = self.expressions[start + 1..]
+                    .iter()
+                    .position(|expression| {
+                        matches!(expression.payload, ExpressionPayload::Anchor { .. })
+                    })
+                    .map_or(self.expressions.len(), |end| start + 1 + end);
+                self.expressions[start..end].to_vec()
+            }
+
+            None => vec![self
+                .expressions
+                .iter()
+                .find_map(|expression| expression.containing(anchor))?
+                .clone()],
+        };
+
+        Some(Page {
+            styles: self.styles.clone(),
+            expressions,
+        })
+    }
 }
 
 /// Map from token to list of styles.
 pub type PageStyles<'a> = HashMap<Token<'a>, Vec<InlineStyle<'a>>>;
 
 /// An expression in the page.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct PageExpression<'a> {
     /// The builtin expression type
     pub builtin: Token<'a>,
@@ -44,7 +88,37 @@ pub struct PageExpression<'a> {
     pub payload: ExpressionPayload<'a>,
 }
 
-impl PageExpression<'_> {
+impl<'a> PageExpression<'a> {
+    fn is_anchor(&self, name: &str) -> bool {
+        matches!(&self.payload, ExpressionPayload::Anchor { anchor } if anchor.lexeme() == name)
+    }
+
+    // the innermost expression with the anchor as one of its children
+    fn containing(&self, anchor: &str) -> Option<&PageExpression<'a>> {
+        match &self.payload {
+            ExpressionPayload::Children { children, .. } => {
+                if children.iter().any(|child| child.is_anchor(anchor)) {
+                    Some(self)
+                } else {
+                    children.iter().find_map(|child| child.containing(anchor))
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn anchors(&self, anchors: &mut Vec<String>) {
+        match &self.payload {
+            ExpressionPayload::Anchor { anchor } => anchors.push(anchor.clone_lexeme()),
+            ExpressionPayload::Children { children, .. } => {
+                for child in children.iter() {
+                    child.anchors(anchors)
+                }
+            }
+            _ => {}
+        }
+    }
+
     fn item_names(&self, names: &mut Vec<String>) {
         match &self.payload {
             ExpressionPayload::Blob { name, .. } => names.push(name.clone_lexeme()),
@@ -59,7 +133,7 @@ impl PageExpression<'_> {
 }
 
 /// Content of a page expression.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum ExpressionPayload<'a> {
     /// Text
     Text {
@@ -95,7 +169,7 @@ pub enum ExpressionPayload<'a> {
 }
 
 /// A style.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum InlineStyle<'a> {
     /// Monospace font
     Mono {
@@ -167,6 +241,102 @@ pub enum InlineStyle<'a> {
     },
 }
 
+/// Convert a page back into froggi markup.
+///
+/// Comments and formatting are lost. Page styles are written in order of their selectors, so the
+/// same page is always written the same way.
+pub fn to_markup(page: &Page) -> String {
+    let mut markup = String::new();
+
+    if !page.styles.is_empty() {
+        let mut selectors: Vec<_> = page.styles.keys().collect();
+        selectors.sort_by(|a, b| a.lexeme().cmp(b.lexeme()));
+
+        markup.push('{');
+        for selector in selectors {
+            markup.push('(');
+            markup.push_str(selector.lexeme());
+            for inline_style in &page.styles[selector] {
+                markup.push(' ');
+                markup.push_str(&inline_style_to_markup(inline_style));
+            }
+            markup.push(')');
+        }
+        markup.push_str("}\n");
+    }
+
+    for expression in &page.expressions {
+        markup.push_str(&page_expression_to_markup(expression));
+        markup.push('\n');
+    }
+
+    markup
+}
+
+fn page_expression_to_markup(expression: &PageExpression) -> String {
+    let mut parts = Vec::new();
+
+    // implicit text has an empty builtin
+    if !expression.builtin.lexeme().is_empty() {
+        parts.push(expression.builtin.clone_lexeme());
+    }
+
+    match &expression.payload {
+        ExpressionPayload::Link { link: name, .. }
+        | ExpressionPayload::Blob { name, .. }
+        | ExpressionPayload::Anchor { anchor: name } => parts.push(quote(name)),
+        _ => {}
+    }
+
+    if !expression.styles.is_empty() {
+        let styles: Vec<_> = expression
+            .styles
+            .iter()
+            .map(inline_style_to_markup)
+            .collect();
+        parts.push(format!("{{{}}}", styles.join(" ")));
+    }
+
+    match &expression.payload {
+        ExpressionPayload::Text { text }
+        | ExpressionPayload::Link { text, .. }
+        | ExpressionPayload::Blob { alt: text, .. } => parts.extend(text.iter().map(quote)),
+        ExpressionPayload::Children { children, .. } => {
+            parts.extend(children.iter().map(page_expression_to_markup))
+        }
+        ExpressionPayload::Anchor { .. } => {}
+    }
+
+    format!("({})", parts.join(" "))
+}
+
+fn inline_style_to_markup(style: &InlineStyle) -> String {
+    match style {
+        InlineStyle::Mono { token }
+        | InlineStyle::Serif { token }
+        | InlineStyle::Sans { token }
+        | InlineStyle::Bold { token }
+        | InlineStyle::Italic { token }
+        | InlineStyle::Underline { token }
+        | InlineStyle::Strike { token }
+        | InlineStyle::UserDefined { token } => token.clone_lexeme(),
+        InlineStyle::Fg { token, arg } | InlineStyle::Bg { token, arg } => format!(
+            "({} \"{:02x}{:02x}{:02x}\")",
+            token.lexeme(),
+            arg.0,
+            arg.1,
+            arg.2
+        ),
+        InlineStyle::Fill { token, arg } => format!("({} \"{}\")", token.lexeme(), arg),
+        InlineStyle::Size { token, arg } => format!("({} \"{}\")", token.lexeme(), arg),
+    }
+}
+
+// strings can't contain either kind of quote, so they never need escaping
+fn quote(token: &Token) -> String {
+    format!("\"{}\"", token.lexeme())
+}
+
 /// Convert a page into HTML
 pub fn to_html(page: &Page) -> String {
     let mut html = String::from(
@@ -411,3 +581,64 @@ fn inline_style_to_html(style: &InlineStyle) -> String {
         InlineStyle::UserDefined { .. } => unreachable!(),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::parse::parse;
+    use super::*;
+
+    const PAGE: &str = r#"{(bold-red bold (fg "ff0000"))}
+(# "top")
+("welcome")
+(& "frog.png" "a frog")
+(# "pond")
+(wide {(fill "2")} (& "lily.png") (tall (# "deep") ({bold-red} "deep") (& "fish.png")))
+(^ "index.fml" {italic} "home")
+(# "bottom")
+("bye")
+"#;
+
+    #[test]
+    fn fragments() {
+        let page = parse(PAGE).unwrap();
+
+        let top = page.fragment("top").unwrap();
+        assert_eq!(top.expressions.len(), 3);
+        assert_eq!(top.item_names(), vec![String::from("frog.png")]);
+        assert_eq!(top.styles, page.styles);
+
+        let pond = page.fragment("pond").unwrap();
+        assert_eq!(pond.expressions.len(), 3);
+        assert_eq!(
+            pond.item_names(),
+            vec![String::from("lily.png"), String::from("fish.png")]
+        );
+
+        // the innermost expression around a nested anchor
+        let deep = page.fragment("deep").unwrap();
+        assert_eq!(deep.expressions.len(), 1);
+        assert_eq!(deep.expressions[0].builtin.kind(), TokenKind::Tall);
+        assert_eq!(deep.item_names(), vec![String::from("fish.png")]);
+
+        assert_eq!(page.fragment("bottom").unwrap().expressions.len(), 2);
+        assert!(page.fragment("lake").is_none());
+        assert!(page.fragment("frog.png").is_none());
+
+        assert_eq!(page.anchors(), vec!["top", "pond", "deep", "bottom"]);
+    }
+
+    #[test]
+    fn markup() {
+        let page = parse(PAGE).unwrap();
+        let markup = to_markup(&page);
+        assert_eq!(parse(&markup).unwrap(), page);
+        assert_eq!(to_markup(&parse(&markup).unwrap()), markup);
+
+        let deep = to_markup(&page.fragment("deep").unwrap());
+        assert_eq!(
+            deep,
+            "{(bold-red bold (fg \"ff0000\"))}\n\
+             (tall (# \"deep\") ({bold-red} \"deep\") (& \"fish.png\"))\n"
+        );
+    }
+}
@@ -0,0 +1,189 @@
+//! Working out what kind of item some data is.
+//!
+//! Most formats start with a few magic bytes that give them away. Anything else is plain text if
+//! it looks like text, and a binary download if it doesn't. Stylesheets are text with nothing to
+//! tell them apart, so they're only recognized by name.
+
+use crate::response::ItemKind;
+
+// magic bytes at the start of data, and what they mean
+#[rustfmt::skip]
+const MAGIC: &[(&[u8], ItemKind, &str)] = &[
+    (b"\x89PNG\r\n\x1a\n",  ItemKind::Image,  "image/png"),
+    (b"\xff\xd8\xff",       ItemKind::Image,  "image/jpeg"),
+    (b"GIF87a",             ItemKind::Image,  "image/gif"),
+    (b"GIF89a",             ItemKind::Image,  "image/gif"),
+    (b"\x00\x00\x01\x00",   ItemKind::Image,  "image/x-icon"),
+    (b"qoif",               ItemKind::Image,  "image/qoi"),
+    (b"OggS",               ItemKind::Audio,  "audio/ogg"),
+    (b"fLaC",               ItemKind::Audio,  "audio/flac"),
+    (b"ID3",                ItemKind::Audio,  "audio/mpeg"),
+    (b"\xff\xfb",           ItemKind::Audio,  "audio/mpeg"),
+    (b"\xff\xf3",           ItemKind::Audio,  "audio/mpeg"),
+    (b"\xff\xf2",           ItemKind::Audio,  "audio/mpeg"),
+    (b"MThd\0\0\0\x06",      ItemKind::Audio,  "audio/midi"),
+    (b"\x1a\x45\xdf\xa3",   ItemKind::Video,  "video/webm"),
+    (b"\x00\x01\x00\x00",   ItemKind::Font,   "font/ttf"),
+    (b"OTTO\x00",           ItemKind::Font,   "font/otf"),
+    (b"wOFF",               ItemKind::Font,   "font/woff"),
+    (b"wOF2",               ItemKind::Font,   "font/woff2"),
+    (b"%PDF-",              ItemKind::Binary, "application/pdf"),
+    (b"PK\x03\x04",         ItemKind::Binary, "application/zip"),
+    (b"\x1f\x8b",           ItemKind::Binary, "application/gzip"),
+];
+
+// RIFF files say what they are in bytes 8 to 12
+#[rustfmt::skip]
+const RIFF: &[(&[u8], ItemKind, &str)] = &[
+    (b"WEBP", ItemKind::Image, "image/webp"),
+    (b"WAVE", ItemKind::Audio, "audio/wav"),
+    (b"AVI ", ItemKind::Video, "video/x-msvideo"),
+];
+
+// ISO media files have a brand in bytes 8 to 12, after the "ftyp" box name
+#[rustfmt::skip]
+const ISO_BRANDS: &[(&[u8], ItemKind, &str)] = &[
+    (b"M4A ", ItemKind::Audio, "audio/mp4"),
+    (b"avif", ItemKind::Image, "image/avif"),
+    (b"qt  ", ItemKind::Video, "video/quicktime"),
+];
+
+// names that say more than the data does
+#[rustfmt::skip]
+const EXTENSIONS: &[(&str, ItemKind, &str)] = &[
+    ("css", ItemKind::Stylesheet, "text/css"),
+    ("md",  ItemKind::Text,       "text/markdown"),
+];
+
+/// Work out the kind and media type of some data from its first few bytes.
+pub fn sniff(data: &[u8]) -> (ItemKind, &'static str) {
+    for (magic, kind, media_type) in MAGIC {
+        if data.starts_with(magic) {
+            return (*kind, media_type);
+        }
+    }
+
+    let brand = data.get(8..12).unwrap_or_default();
+
+    if data.starts_with(b"RIFF") {
+        for (riff, kind, media_type) in RIFF {
+            if brand == *riff {
+                return (*kind, media_type);
+            }
+        }
+    }
+
+    if data.get(4..8) == Some(b"ftyp") {
+        for (iso, kind, media_type) in ISO_BRANDS {
+            if brand == *iso {
+                return (*kind, media_type);
+            }
+        }
+
+        return (ItemKind::Video, "video/mp4");
+    }
+
+    match std::str::from_utf8(data) {
+        Ok(text) if is_svg(text) => (ItemKind::Image, "image/svg+xml"),
+        Ok(text) if is_text(text) => (ItemKind::Text, "text/plain"),
+        _ => (ItemKind::Binary, "application/octet-stream"),
+    }
+}
+
+/// Work out the kind and media type of a file from its data, or its name if the data could be
+/// anything.
+///
+/// The name only matters for text, since that's the only kind of data that doesn't say what it
+/// is.
+pub fn sniff_named(name: &str, data: &[u8]) -> (ItemKind, &'static str) {
+    let sniffed = sniff(data);
+    if sniffed.0 != ItemKind::Text {
+        return sniffed;
+    }
+
+    if let Some((_, extension)) = name.rsplit_once('.') {
+        for (known, kind, media_type) in EXTENSIONS {
+            if extension.eq_ignore_ascii_case(known) {
+                return (*kind, media_type);
+            }
+        }
+    }
+
+    sniffed
+}
+
+// no control characters other than whitespace, and at least something
+fn is_text(text: &str) -> bool {
+    !text.is_empty()
+        && text
+            .chars()
+            .all(|c| !c.is_control() || matches!(c, '\t' | '\n' | '\r' | '\x0c'))
+}
+
+fn is_svg(text: &str) -> bool {
+    let text = text.trim_start_matches('\u{feff}').trim_start();
+    text.starts_with("<svg") || (text.starts_with("<?xml") && text.contains("<svg"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn magic() {
+        assert_eq!(
+            sniff(include_bytes!("../1px_white.png")),
+            (ItemKind::Image, "image/png")
+        );
+        assert_eq!(
+            sniff(b"RIFF\x24\x00\x00\x00WAVEfmt "),
+            (ItemKind::Audio, "audio/wav")
+        );
+        assert_eq!(
+            sniff(b"\x00\x00\x00\x18ftypisom"),
+            (ItemKind::Video, "video/mp4")
+        );
+        assert_eq!(
+            sniff(b"\x00\x00\x00\x18ftypM4A "),
+            (ItemKind::Audio, "audio/mp4")
+        );
+        assert_eq!(sniff(b"wOF2\x00\x01"), (ItemKind::Font, "font/woff2"));
+        assert_eq!(sniff(b"%PDF-1.7"), (ItemKind::Binary, "application/pdf"));
+    }
+
+    #[test]
+    fn text() {
+        assert_eq!(sniff(b"ribbit\n"), (ItemKind::Text, "text/plain"));
+        assert_eq!(
+            sniff(b"<?xml version=\"1.0\"?>\n<svg></svg>"),
+            (ItemKind::Image, "image/svg+xml")
+        );
+        assert_eq!(
+            sniff(b"rib\x00bit"),
+            (ItemKind::Binary, "application/octet-stream")
+        );
+        assert_eq!(
+            sniff(b"\xffribbit"),
+            (ItemKind::Binary, "application/octet-stream")
+        );
+        assert_eq!(sniff(b""), (ItemKind::Binary, "application/octet-stream"));
+    }
+
+    #[test]
+    fn named() {
+        assert_eq!(
+            sniff_named("pond.CSS", b"body {}"),
+            (ItemKind::Stylesheet, "text/css")
+        );
+        assert_eq!(
+            sniff_named("notes.txt", b"body {}"),
+            (ItemKind::Text, "text/plain")
+        );
+
+        // the data wins over the name
+        assert_eq!(
+            sniff_named("white.css", include_bytes!("../1px_white.png")),
+            (ItemKind::Image, "image/png")
+        );
+    }
+}
@@ -37,6 +37,44 @@ pub const REQUEST_LENGTH_OFFSET: usize = FROGGI_HEADER_LEN;
 /// The byte offset of the request string.
 pub const REQUEST_OFFSET: usize = FROGGI_HEADER_LEN + REQUEST_LENGTH_LEN;
 
+/// The bits of the request kind byte that are the request kind. The rest are flags.
+pub const REQUEST_KIND_MASK: u8 = 0x0f;
+/// Request flag for a content hash of the client's cached copy after the body.
+pub const REQUEST_FLAG_CACHED: u8 = 0x10;
+/// Request flag for a list of item hashes the client already has, after the cached hash.
+pub const REQUEST_FLAG_KNOWN_ITEMS: u8 = 0x20;
+/// Request flag asking the server to keep the connection open after responding.
+pub const REQUEST_FLAG_KEEP_ALIVE: u8 = 0x40;
+/// Request flag for a block of extensions after the known items.
+pub const REQUEST_FLAG_EXTENSIONS: u8 = 0x80;
+/// Every request flag.
+pub const REQUEST_FLAGS: u8 = REQUEST_FLAG_CACHED
+    | REQUEST_FLAG_KNOWN_ITEMS
+    | REQUEST_FLAG_KEEP_ALIVE
+    | REQUEST_FLAG_EXTENSIONS;
+
+/// The byte length of the number of known item hashes.
+pub const KNOWN_ITEMS_COUNT_LEN: usize = 2;
+
+/// The byte length of the number of extensions.
+pub const EXTENSION_COUNT_LEN: usize = 1;
+/// The byte length of an extension key.
+pub const EXTENSION_KEY_LEN: usize = 1;
+/// The byte length of an extension value's length.
+pub const EXTENSION_LENGTH_LEN: usize = 2;
+/// The byte length of the key and length before each extension value.
+pub const EXTENSION_HEADER_LEN: usize = EXTENSION_KEY_LEN + EXTENSION_LENGTH_LEN;
+
+/// The byte length of a content hash.
+pub const CONTENT_HASH_LEN: usize = 32;
+
+/// The byte length of the request body kind, which follows the request string in put requests.
+pub const REQUEST_BODY_KIND_LEN: usize = 1;
+/// The byte length of the request body length.
+pub const REQUEST_BODY_LENGTH_LEN: usize = 4;
+/// The byte length of the request body header, relative to the end of the request string.
+pub const REQUEST_BODY_HEADER_LEN: usize = REQUEST_BODY_KIND_LEN + REQUEST_BODY_LENGTH_LEN;
+
 // response constants
 
 /// The byte length of the total response length.
@@ -44,12 +82,31 @@ pub const TOTAL_RESPONSE_LENGTH_LEN: usize = 4;
 /// The byte offset of the total response length.
 pub const TOTAL_RESPONSE_LENGTH_OFFSET: usize = FROGGI_HEADER_LEN;
 
+/// The bits of the response kind byte that are the response kind. The rest are flags.
+pub const RESPONSE_KIND_MASK: u8 = 0x0f;
+/// Response flag for item hashes in the item headers.
+pub const RESPONSE_FLAG_ITEM_HASHES: u8 = 0x10;
+/// Response flag for a compression codec before the page length and each item length.
+pub const RESPONSE_FLAG_COMPRESSED: u8 = 0x20;
+/// Response flag for the page and item data being left out, leaving only their lengths.
+pub const RESPONSE_FLAG_METADATA: u8 = 0x40;
+/// Response flag for a media type after each item's name.
+pub const RESPONSE_FLAG_MEDIA_TYPES: u8 = 0x80;
+/// Every response flag.
+pub const RESPONSE_FLAGS: u8 = RESPONSE_FLAG_ITEM_HASHES
+    | RESPONSE_FLAG_COMPRESSED
+    | RESPONSE_FLAG_METADATA
+    | RESPONSE_FLAG_MEDIA_TYPES;
+
+/// The byte length of the error code, which follows the total response length in error responses.
+pub const ERROR_CODE_LEN: usize = 1;
+
 /// The byte length of the page length.
 pub const PAGE_LENGTH_LEN: usize = 4;
-/// The byte offset of the page length.
+/// The byte offset of the page length. Error responses have it `ERROR_CODE_LEN` bytes later.
 pub const PAGE_LENGTH_OFFSET: usize = FROGGI_HEADER_LEN + TOTAL_RESPONSE_LENGTH_LEN;
 
-/// The byte offset of the page.
+/// The byte offset of the page. Error responses have it `ERROR_CODE_LEN` bytes later.
 pub const PAGE_OFFSET: usize = FROGGI_HEADER_LEN + TOTAL_RESPONSE_LENGTH_LEN + PAGE_LENGTH_LEN;
 
 /// The byte length of the number of items.
@@ -60,6 +117,19 @@ pub const ITEM_KIND_LEN: usize = 1;
 pub const ITEM_NAME_LENGTH_LEN: usize = 1;
 /// The byte length of the item length.
 pub const ITEM_LENGTH_LEN: usize = 4;
+/// The byte length of the item media type length, in responses with media types.
+pub const MEDIA_TYPE_LENGTH_LEN: usize = 1;
+/// The byte length of the compression codec, in compressed responses.
+pub const COMPRESSION_LEN: usize = 1;
+/// The byte length of the offset of part of an item, in item requests and item range responses.
+pub const ITEM_OFFSET_LEN: usize = 4;
+
+// version 1 constants. other lengths are varints, see the codec module
+
+/// The byte length of the total response length in version 1.
+pub const TOTAL_RESPONSE_LENGTH_LEN_V1: usize = 8;
+/// The most bytes a varint length can take up.
+pub const VARINT_MAX_LEN: usize = 10;
 
 #[cfg(test)]
 mod test {
@@ -91,6 +161,8 @@ mod test {
         assert_eq!(2, REQUEST_LENGTH_LEN);
         assert_eq!(22, REQUEST_LENGTH_OFFSET);
         assert_eq!(24, REQUEST_OFFSET);
+        assert_eq!(5, REQUEST_BODY_HEADER_LEN);
+        assert_eq!(3, EXTENSION_HEADER_LEN);
     }
 
     #[test]
@@ -0,0 +1,281 @@
+//! Following redirect responses.
+//!
+//! A redirect response has the URL of the page's new home as its page. The URL may be relative
+//! to the URL that was requested.
+
+use crate::response::Response;
+use crate::url::Url;
+use crate::{ErrorKind, FroggiError, RedirectError, Uuid};
+
+/// Which redirects a client is willing to follow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RedirectPolicy {
+    /// Most redirects to follow for a single request
+    pub max_hops: usize,
+    /// Whether to follow redirects to a different scheme, host or port
+    pub allow_cross_host: bool,
+}
+
+impl Default for RedirectPolicy {
+    fn default() -> Self {
+        RedirectPolicy {
+            max_hops: 5,
+            allow_cross_host: false,
+        }
+    }
+}
+
+impl RedirectPolicy {
+    /// Don't follow any redirects, hand them back as they are.
+    pub fn none() -> Self {
+        RedirectPolicy {
+            max_hops: 0,
+            allow_cross_host: false,
+        }
+    }
+
+    /// Figure out where a redirect from `from` to `target` goes, if we're allowed to go there.
+    ///
+    /// `visited` is every URL requested so far, including `from`. If the target has no fragment,
+    /// it keeps the fragment of `from`.
+    pub fn next(&self, visited: &[Url], from: &Url, target: &str) -> Result<Url, FroggiError> {
+        // the first URL in visited wasn't a redirect
+        if visited.len() > self.max_hops {
+            return Err(redirect_error(RedirectError::TooManyRedirects {
+                max: self.max_hops,
+            }));
+        }
+
+        let mut to = from.resolve(target)?;
+        if to.fragment().is_none() {
+            if let Some(fragment) = from.fragment() {
+                to = to.resolve(&format!("#{}", fragment))?;
+            }
+        }
+
+        if !self.allow_cross_host && !to.same_server(from) {
+            return Err(redirect_error(RedirectError::CrossHost {
+                from: from.to_string(),
+                to: to.to_string(),
+            }));
+        }
+
+        // fragments don't change what gets requested
+        if visited
+            .iter()
+            .any(|url| url.without_fragment() == to.without_fragment())
+        {
+            return Err(redirect_error(RedirectError::Loop {
+                url: to.to_string(),
+            }));
+        }
+
+        Ok(to)
+    }
+}
+
+/// Send a request, following redirects according to the policy.
+///
+/// `send` sends a single request for a URL with a client ID. The client ID is only sent to the
+/// server it came from, with the same scheme, so it's never sent in plaintext after TLS. Returns the URL the final response came from along with the response. If
+/// the policy doesn't follow any redirects, redirect responses are returned as they are.
+pub fn follow(
+    url: &Url,
+    id: Uuid,
+    policy: RedirectPolicy,
+    mut send: impl FnMut(&Url, Uuid) -> Result<Response, FroggiError>,
+) -> Result<(Url, Response), FroggiError> {
+    let mut visited = vec![url.clone()];
+
+    loop {
+        // unwrap safety - visited always has at least one URL
+        let current = visited.last().unwrap();
+        let id = if current.same_server(url) {
+            id
+        } else {
+            Uuid::nil()
+        };
+
+        let response = send(current, id)?;
+        let target = match response.redirect_target() {
+            Some(target) if policy.max_hops != 0 => target,
+            _ => return Ok((current.clone(), response)),
+        };
+
+        let next = policy.next(&visited, current, target)?;
+        visited.push(next);
+    }
+}
+
+fn redirect_error(error: RedirectError) -> FroggiError {
+    FroggiError::new(ErrorKind::RedirectError { error })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::response::{ResponseBuilder, ResponseKind};
+
+    use std::collections::HashMap;
+
+    type Pages = HashMap<&'static str, (ResponseKind, &'static str)>;
+
+    // pretend to be a server with some pages and redirects
+    fn send_to(pages: &Pages) -> impl FnMut(&Url, Uuid) -> Result<Response, FroggiError> + '_ {
+        move |url, _| {
+            let builder = match pages[url.without_fragment().to_string().as_str()] {
+                (ResponseKind::Redirect, target) => ResponseBuilder::default().redirect(target),
+                (_, page) => ResponseBuilder::default().page(page.into()),
+            };
+            builder.build()
+        }
+    }
+
+    fn redirect(target: &'static str) -> (ResponseKind, &'static str) {
+        (ResponseKind::Redirect, target)
+    }
+
+    fn page(page: &'static str) -> (ResponseKind, &'static str) {
+        (ResponseKind::PageNoItems, page)
+    }
+
+    #[test]
+    fn follow_redirects() {
+        let mut pages = HashMap::new();
+        pages.insert("frgi://example.com/old.fml", redirect("new/"));
+        pages.insert("frgi://example.com/new/", redirect("index.fml"));
+        pages.insert("frgi://example.com/new/index.fml", page("('hello')"));
+
+        let url = Url::parse("frgi://example.com/old.fml#top").unwrap();
+        let (url, response) = follow(
+            &url,
+            Uuid::nil(),
+            RedirectPolicy::default(),
+            send_to(&pages),
+        )
+        .unwrap();
+
+        assert_eq!(url.to_string(), "frgi://example.com/new/index.fml#top");
+        assert_eq!(response.page(), "('hello')");
+
+        // too many hops
+        let url = Url::parse("frgi://example.com/old.fml").unwrap();
+        let policy = RedirectPolicy {
+            max_hops: 1,
+            ..RedirectPolicy::default()
+        };
+        assert!(matches!(
+            follow(&url, Uuid::nil(), policy, send_to(&pages))
+                .unwrap_err()
+                .kind(),
+            ErrorKind::RedirectError {
+                error: RedirectError::TooManyRedirects { max: 1 }
+            }
+        ));
+
+        // redirects aren't followed at all
+        let (_, response) =
+            follow(&url, Uuid::nil(), RedirectPolicy::none(), send_to(&pages)).unwrap();
+        assert_eq!(response.redirect_target(), Some("new/"));
+    }
+
+    #[test]
+    fn redirect_loop() {
+        let mut pages = HashMap::new();
+        pages.insert("frgi://example.com/a.fml", redirect("b.fml"));
+        pages.insert("frgi://example.com/b.fml", redirect("a.fml#again"));
+
+        let url = Url::parse("frgi://example.com/a.fml").unwrap();
+        assert!(matches!(
+            follow(&url, Uuid::nil(), RedirectPolicy::default(), send_to(&pages))
+                .unwrap_err()
+                .kind(),
+            ErrorKind::RedirectError {
+                error: RedirectError::Loop { url }
+            } if url == "frgi://example.com/a.fml#again"
+        ));
+    }
+
+    #[test]
+    fn cross_host() {
+        let mut pages = HashMap::new();
+        pages.insert(
+            "frgi://example.com/a.fml",
+            redirect("frgi://other.com/a.fml"),
+        );
+        pages.insert("frgi://other.com/a.fml", page("('elsewhere')"));
+
+        let url = Url::parse("frgi://example.com/a.fml").unwrap();
+        assert!(matches!(
+            follow(
+                &url,
+                Uuid::nil(),
+                RedirectPolicy::default(),
+                send_to(&pages)
+            )
+            .unwrap_err()
+            .kind(),
+            ErrorKind::RedirectError {
+                error: RedirectError::CrossHost { .. }
+            }
+        ));
+
+        // the client ID stays with the host that issued it
+        let id = Uuid::new_v4();
+        let mut ids = Vec::new();
+        let policy = RedirectPolicy {
+            allow_cross_host: true,
+            ..RedirectPolicy::default()
+        };
+        let mut send = send_to(&pages);
+        let (url, _) = follow(&url, id, policy, |url, id| {
+            ids.push(id);
+            send(url, id)
+        })
+        .unwrap();
+
+        assert_eq!(url.host(), "other.com");
+        assert_eq!(ids, vec![id, Uuid::nil()]);
+    }
+
+    #[test]
+    fn tls_downgrade() {
+        let mut pages = HashMap::new();
+        pages.insert(
+            "frgis://example.com/a.fml",
+            redirect("frgi://example.com/a.fml"),
+        );
+        pages.insert("frgi://example.com/a.fml", page("('in plaintext')"));
+
+        let url = Url::parse("frgis://example.com/a.fml").unwrap();
+        assert!(matches!(
+            follow(
+                &url,
+                Uuid::nil(),
+                RedirectPolicy::default(),
+                send_to(&pages)
+            )
+            .unwrap_err()
+            .kind(),
+            ErrorKind::RedirectError {
+                error: RedirectError::CrossHost { .. }
+            }
+        ));
+
+        // the client ID from the TLS server isn't sent in plaintext
+        let id = Uuid::new_v4();
+        let mut ids = Vec::new();
+        let policy = RedirectPolicy {
+            allow_cross_host: true,
+            ..RedirectPolicy::default()
+        };
+        let mut send = send_to(&pages);
+        follow(&url, id, policy, |url, id| {
+            ids.push(id);
+            send(url, id)
+        })
+        .unwrap();
+
+        assert_eq!(ids, vec![id, Uuid::nil()]);
+    }
+}
@@ -1,53 +1,123 @@
 //! Types for dealing with a froggi protocol request.
 
+use crate::codec::{Codec, Field};
+use crate::compression::Compression;
+use crate::extension::{Extension, ExtensionKey};
+use crate::hash::ContentHash;
+use crate::limits::{DecodeLimits, Limit};
+use crate::response::ItemKind;
 use crate::{protocol::*, serialize_to_bytes, AddMsg, ErrorKind, FroggiError, Uuid};
+use froggi_derive::U8Enum;
 
 use std::convert::TryInto;
-use std::io::Read;
-
-// TODO proc macro
-// /// Kinds of requests.
-// ///
-// /// PageOnly - Plain old page. Don't send me any items or additional page expressions.
-// /// I won't be talking to you again.
-// /// PageItems - Page with items, but no interactions. Don't send me any additional page
-// /// expressions. I won't be talking to you again.
-// /// Page - Give me everything. I'll be in touch again for those extra page
-// /// expressions.
-// /// Put - Here's some data. I'm eagerly awaiting your response.
-crate::u8enum! { RequestKind {
+use std::io::{Read, Write};
+
+#[cfg(feature = "async")]
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Kinds of requests.
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, U8Enum)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RequestKind {
+    /// Plain old page. Don't send me any items or additional page expressions. I won't be
+    /// talking to you again.
     PageOnly = 0,
+    /// Page with items, but no interactions. Don't send me any additional page expressions. I
+    /// won't be talking to you again.
     PageItems = 1,
+    /// Give me everything. I'll be in touch again for those extra page expressions.
     Page = 2,
+    /// Just tell me about the page. Don't send me the page or its items, only their sizes and
+    /// hashes.
+    Metadata = 3,
+    /// Just send me one of the page's items, or part of it.
+    Item = 4,
+    /// Here's some data. I'm eagerly awaiting your response.
     Put = 14,
-    Unknown = 15,
-} }
+    /// A kind we don't know about. The spec reserves 15 for this.
+    Unknown(u8),
+}
 
 /// Represents a froggi request to a server.
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "RequestFields"))]
 pub struct Request {
     version: u8,
     kind: RequestKind,
     id: Uuid,
     request: String,
+    body_kind: ItemKind,
+    #[cfg_attr(feature = "serde", serde(with = "crate::compact"))]
+    body: Vec<u8>,
+    item_name: String,
+    item_offset: usize,
+    item_len: Option<usize>,
+    cached: Option<ContentHash>,
+    known_items: Vec<ContentHash>,
+    keep_alive: bool,
+    extensions: Vec<Extension>,
+}
+
+// deserialized requests go through the same checks as ones built by hand
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct RequestFields {
+    version: u8,
+    kind: RequestKind,
+    id: Uuid,
+    request: String,
+    body_kind: ItemKind,
+    #[serde(with = "crate::compact")]
+    body: Vec<u8>,
+    item_name: String,
+    item_offset: usize,
+    item_len: Option<usize>,
+    cached: Option<ContentHash>,
+    known_items: Vec<ContentHash>,
+    keep_alive: bool,
+    extensions: Vec<Extension>,
+}
+
+#[cfg(feature = "serde")]
+impl std::convert::TryFrom<RequestFields> for Request {
+    type Error = FroggiError;
+
+    fn try_from(fields: RequestFields) -> Result<Request, FroggiError> {
+        let request = match fields.kind {
+            RequestKind::Put => {
+                Request::new_put(fields.request, fields.id, fields.body_kind, fields.body)?
+            }
+            RequestKind::Item => Request::new_item(
+                fields.request,
+                fields.id,
+                fields.item_name,
+                fields.item_offset,
+                fields.item_len,
+            )?,
+            kind => Request::new_with_id(fields.request, fields.id, kind)?,
+        };
+
+        let mut request = fields
+            .extensions
+            .into_iter()
+            .try_fold(request, |request, extension| {
+                request.with_extension(extension.key(), extension.value().to_vec())
+            })?
+            .with_known_items(fields.known_items)?
+            .with_version(fields.version)?;
+
+        request.cached = fields.cached;
+        request.keep_alive = fields.keep_alive;
+        Ok(request)
+    }
 }
 
 impl Request {
     /// Create a new request with no client ID.
     pub fn new(request: impl ToString, kind: RequestKind) -> Result<Self, FroggiError> {
-        let request = request.to_string();
-        let version = crate::FROGGI_VERSION;
-
-        if request.len() > u16::MAX as usize {
-            Err(FroggiError::new(ErrorKind::RequestFormatError).msg_str("The path is too large."))
-        } else {
-            Ok(Request {
-                version,
-                kind,
-                id: Uuid::nil(),
-                request,
-            })
-        }
+        Self::new_with_id(request, Uuid::nil(), kind)
     }
 
     /// Create a new request with a client ID.
@@ -56,32 +126,232 @@ impl Request {
         id: Uuid,
         kind: RequestKind,
     ) -> Result<Self, FroggiError> {
-        let request = request.to_string();
-        let version = crate::FROGGI_VERSION;
+        Request {
+            version: crate::FROGGI_VERSIONS[0],
+            kind,
+            id,
+            request: request.to_string(),
+            body_kind: ItemKind::Unknown(15),
+            body: Vec::new(),
+            item_name: String::new(),
+            item_offset: 0,
+            item_len: None,
+            cached: None,
+            known_items: Vec::new(),
+            keep_alive: false,
+            extensions: Vec::new(),
+        }
+        .in_oldest_version()
+    }
 
-        if request.len() > u16::MAX as usize {
-            Err(FroggiError::new(ErrorKind::RequestFormatError).msg_str("The path is too large."))
-        } else {
-            Ok(Request {
-                version,
-                kind,
-                id,
-                request,
-            })
+    /// Create a new put request, which sends some data along with the path.
+    pub fn new_put(
+        request: impl ToString,
+        id: Uuid,
+        body_kind: ItemKind,
+        body: Vec<u8>,
+    ) -> Result<Self, FroggiError> {
+        Request {
+            body_kind,
+            body,
+            ..Request::new_with_id(request, id, RequestKind::Put)?
+        }
+        .in_oldest_version()
+    }
+
+    /// Create a new item request, which asks for one of a page's items by name.
+    ///
+    /// Only the part of the item starting at `offset` is sent, up to `len` bytes if there's a
+    /// length, or to the end of the item if there isn't.
+    pub fn new_item(
+        request: impl ToString,
+        id: Uuid,
+        item_name: impl ToString,
+        offset: usize,
+        len: Option<usize>,
+    ) -> Result<Self, FroggiError> {
+        if len == Some(0) {
+            return Err(
+                FroggiError::new(ErrorKind::RequestFormatError).msg_str("The item range is empty.")
+            );
+        }
+
+        Request {
+            item_name: item_name.to_string(),
+            item_offset: offset,
+            item_len: len,
+            ..Request::new_with_id(request, id, RequestKind::Item)?
+        }
+        .in_oldest_version()
+    }
+
+    /// Write the request in another version of froggi.
+    ///
+    /// Requests are written in the oldest version they fit in by default, since servers that
+    /// only speak version 0 can't tell they've been sent a newer one. Older versions can't hold as
+    /// much, so this fails if the request doesn't fit.
+    pub fn with_version(self, version: u8) -> Result<Self, FroggiError> {
+        Request { version, ..self }.checked()
+    }
+
+    // servers from before version 1 don't look at the version, so new requests are written in the
+    // oldest version that can hold them
+    fn in_oldest_version(mut self) -> Result<Self, FroggiError> {
+        for &version in crate::FROGGI_VERSIONS.iter() {
+            self.version = version;
+            if self.check().is_ok() {
+                return Ok(self);
+            }
         }
+        self.checked()
+    }
+
+    fn checked(self) -> Result<Self, FroggiError> {
+        self.check()?;
+        Ok(self)
     }
 
-    /// Read a request from a source of bytes.
+    // make sure everything fits in the request's version
+    fn check(&self) -> Result<(), FroggiError> {
+        let codec = Codec::for_version(self.version)?;
+        let too_large = |message| FroggiError::new(ErrorKind::RequestFormatError).msg_str(message);
+
+        // the high bits of the kind byte are flags
+        let kind: u8 = self.kind.into();
+        if kind & !REQUEST_KIND_MASK != 0 {
+            return Err(FroggiError::new(ErrorKind::RequestFormatError)
+                .msg(format!("The request kind {:#04x} is too large.", kind)));
+        }
+
+        if codec.check(Field::Path, self.request.len()).is_err() {
+            return Err(too_large("The path is too large."));
+        }
+
+        if codec.check(Field::Body, self.body.len()).is_err() {
+            return Err(too_large("The body is too large."));
+        }
+
+        if codec.check(Field::ItemName, self.item_name.len()).is_err()
+            || codec.check(Field::Offset, self.item_offset).is_err()
+            || codec
+                .check(Field::Item, self.item_len.unwrap_or_default())
+                .is_err()
+        {
+            return Err(too_large("The item range is too large."));
+        }
+
+        if codec
+            .check(Field::KnownItems, self.known_items.len())
+            .is_err()
+        {
+            return Err(too_large("There are too many known items."));
+        }
+
+        Ok(())
+    }
+
+    // unwrap safety - the version is checked when the request is created or read
+    fn codec(&self) -> Codec {
+        Codec::for_version(self.version).unwrap()
+    }
+
+    /// Tell the server which version of the page we already have.
+    ///
+    /// If the page hasn't changed, the server responds with `ResponseKind::NotModified` instead of
+    /// sending it again.
+    pub fn with_cached_hash(self, hash: ContentHash) -> Self {
+        Request {
+            cached: Some(hash),
+            ..self
+        }
+    }
+
+    /// Tell the server which items we already have, so it doesn't send them again.
+    ///
+    /// Items the server leaves out of its response can be filled back in with
+    /// `Response::fill_items`.
+    pub fn with_known_items(self, known_items: Vec<ContentHash>) -> Result<Self, FroggiError> {
+        Request {
+            known_items,
+            ..self
+        }
+        .checked()
+    }
+
+    /// Ask the server to leave the connection open for another request after it responds.
+    pub fn with_keep_alive(self) -> Self {
+        Request {
+            keep_alive: true,
+            ..self
+        }
+    }
+
+    /// Add an extension, replacing any other extension with the same key.
+    pub fn with_extension(self, key: impl Into<u8>, value: Vec<u8>) -> Result<Self, FroggiError> {
+        let extension = Extension::new(key, value)?;
+        let mut extensions = self.extensions;
+        extensions.retain(|other| other.key() != extension.key());
+
+        if extensions.len() >= u8::MAX as usize {
+            return Err(FroggiError::new(ErrorKind::RequestFormatError)
+                .msg_str("There are too many extensions."));
+        }
+        extensions.push(extension);
+
+        Ok(Request { extensions, ..self })
+    }
+
+    /// Tell the server the name and version of the client.
+    pub fn with_user_agent(self, user_agent: &str) -> Result<Self, FroggiError> {
+        self.with_extension(ExtensionKey::UserAgent, user_agent.as_bytes().to_vec())
+    }
+
+    /// Tell the server which languages the client would like pages in, like `"en-US, fr"`.
+    pub fn with_language(self, language: &str) -> Result<Self, FroggiError> {
+        self.with_extension(ExtensionKey::Language, language.as_bytes().to_vec())
+    }
+
+    /// Tell the server which kinds of items the client can display.
+    pub fn with_accepted_items(self, kinds: &[ItemKind]) -> Result<Self, FroggiError> {
+        let kinds = kinds.iter().map(|&kind| kind.into()).collect();
+        self.with_extension(ExtensionKey::AcceptItems, kinds)
+    }
+
+    /// Tell the server which compression codecs the client can decompress, most preferred
+    /// first.
+    pub fn with_accepted_compression(self, codecs: &[Compression]) -> Result<Self, FroggiError> {
+        let codecs = codecs.iter().map(|&codec| codec.into()).collect();
+        self.with_extension(ExtensionKey::AcceptCompression, codecs)
+    }
+
+    /// Tell the server how large the client's screen is, in pixels.
+    pub fn with_screen_size(self, width: u16, height: u16) -> Result<Self, FroggiError> {
+        let mut size = width.to_le_bytes().to_vec();
+        size.extend_from_slice(&height.to_le_bytes());
+        self.with_extension(ExtensionKey::ScreenSize, size)
+    }
+
+    /// Read a request from a source of bytes, with the default limits.
     pub fn from_bytes(bytes: &mut impl Read) -> Result<Self, FroggiError> {
-        // request header, twenty bytes
-        let mut header = [0u8; REQUEST_OFFSET];
+        Self::from_bytes_with_limits(bytes, DecodeLimits::default())
+    }
+
+    /// Read a request from a source of bytes, refusing to read a body larger than the limit.
+    pub fn from_bytes_with_limits(
+        bytes: &mut impl Read,
+        limits: DecodeLimits,
+    ) -> Result<Self, FroggiError> {
+        // request header, 22 bytes
+        let mut header = [0u8; FROGGI_HEADER_LEN];
         bytes.read_exact(&mut header)?;
+        let codec = crate::check_header(&header)?;
 
         // first byte is version
         let version = header[FROGGI_VERSION_OFFSET];
 
-        // next byte is request kind
-        let kind = header[REQUEST_RESPONSE_KIND_OFFSET].into();
+        // next byte is request kind, with flags for optional sections in the high bits
+        let kind = (header[REQUEST_RESPONSE_KIND_OFFSET] & REQUEST_KIND_MASK).into();
+        let flags = check_flags(header[REQUEST_RESPONSE_KIND_OFFSET])?;
 
         // next 16 bytes are client id - unwrap is OK since the slice length is definitely 16 bytes
         let id = Uuid::from_bytes(
@@ -90,8 +360,9 @@ impl Request {
                 .unwrap(),
         );
 
-        // next two bytes are request length
-        let request_length = crate::deserialize_bytes(&header[REQUEST_LENGTH_OFFSET..])?;
+        // next is the request length, two bytes in version 0
+        let request_length = codec.read(Field::Path, bytes)?;
+        limits.check(Limit::Path, request_length)?;
 
         // remaining bytes are the request itself
         let mut request_buf = vec![0; request_length];
@@ -99,14 +370,191 @@ impl Request {
 
         let request = String::from_utf8(request_buf)?;
 
+        // put requests have a body after the path
+        let (body_kind, body) = if let RequestKind::Put = kind {
+            let mut body_kind = [0u8; REQUEST_BODY_KIND_LEN];
+            bytes.read_exact(&mut body_kind)?;
+
+            let body_kind = body_kind[0].into();
+            let body_length = codec.read(Field::Body, bytes)?;
+            limits.check(Limit::Body, body_length)?;
+
+            let mut body = vec![0; body_length];
+            bytes.read_exact(&mut body)?;
+
+            (body_kind, body)
+        } else {
+            (ItemKind::Unknown(15), Vec::new())
+        };
+
+        // item requests have the item's name and the range of it to send
+        let (item_name, item_offset, item_len) = if let RequestKind::Item = kind {
+            let name_length = codec.read(Field::ItemName, bytes)?;
+            limits.check(Limit::ItemName, name_length)?;
+
+            let mut name = vec![0; name_length];
+            bytes.read_exact(&mut name)?;
+
+            let offset = codec.read(Field::Offset, bytes)?;
+            let len = codec.read(Field::Item, bytes)?;
+
+            // zero means the rest of the item
+            (
+                String::from_utf8(name)?,
+                offset,
+                Some(len).filter(|&len| len != 0),
+            )
+        } else {
+            (String::new(), 0, None)
+        };
+
+        // then the hash of the client's cached copy, if it has one
+        let cached = if flags & REQUEST_FLAG_CACHED != 0 {
+            let mut hash = [0u8; CONTENT_HASH_LEN];
+            bytes.read_exact(&mut hash)?;
+            Some(ContentHash::from_bytes(hash))
+        } else {
+            None
+        };
+
+        // then the hashes of items the client already has
+        let mut known_items = Vec::new();
+        if flags & REQUEST_FLAG_KNOWN_ITEMS != 0 {
+            let count = codec.read(Field::KnownItems, bytes)?;
+            limits.check(Limit::KnownItems, count)?;
+
+            for _ in 0..count {
+                let mut hash = [0u8; CONTENT_HASH_LEN];
+                bytes.read_exact(&mut hash)?;
+                known_items.push(ContentHash::from_bytes(hash));
+            }
+        }
+
+        // and any extensions come last
+        let mut extensions = Vec::new();
+        if flags & REQUEST_FLAG_EXTENSIONS != 0 {
+            let mut count = [0u8; EXTENSION_COUNT_LEN];
+            bytes.read_exact(&mut count)?;
+
+            let mut extensions_len = 0;
+            for _ in 0..count[0] {
+                let mut header = [0u8; EXTENSION_HEADER_LEN];
+                bytes.read_exact(&mut header)?;
+
+                let value_len = crate::deserialize_bytes(&header[EXTENSION_KEY_LEN..])?;
+                extensions_len += value_len;
+                limits.check(Limit::Extensions, extensions_len)?;
+
+                let mut value = vec![0; value_len];
+                bytes.read_exact(&mut value)?;
+
+                // unwrap safety - the length came from two bytes
+                extensions.push(Extension::new(header[0], value).unwrap());
+            }
+        }
+
         Ok(Request {
             version,
             kind,
             id,
             request,
+            body_kind,
+            body,
+            item_name,
+            item_offset,
+            item_len,
+            cached,
+            known_items,
+            keep_alive: flags & REQUEST_FLAG_KEEP_ALIVE != 0,
+            extensions,
         })
     }
 
+    /// Read a request from an asynchronous source of bytes, with the default limits.
+    #[cfg(feature = "async")]
+    pub async fn from_bytes_async(
+        bytes: &mut (impl AsyncRead + Unpin),
+    ) -> Result<Self, FroggiError> {
+        Self::from_bytes_async_with_limits(bytes, DecodeLimits::default()).await
+    }
+
+    /// Read a request from an asynchronous source of bytes, with limits.
+    #[cfg(feature = "async")]
+    pub async fn from_bytes_async_with_limits(
+        bytes: &mut (impl AsyncRead + Unpin),
+        limits: DecodeLimits,
+    ) -> Result<Self, FroggiError> {
+        // read just enough to know how long each part of the request is, then decode it from
+        // memory
+        let mut data = vec![0; FROGGI_HEADER_LEN];
+        bytes.read_exact(&mut data).await?;
+        let codec = crate::check_header(&data)?;
+        let flags = check_flags(data[REQUEST_RESPONSE_KIND_OFFSET])?;
+
+        let request_length = codec.read_async(Field::Path, bytes, &mut data).await?;
+        limits.check(Limit::Path, request_length)?;
+        read_more(bytes, &mut data, request_length).await?;
+
+        if let RequestKind::Put = (data[REQUEST_RESPONSE_KIND_OFFSET] & REQUEST_KIND_MASK).into() {
+            read_more(bytes, &mut data, REQUEST_BODY_KIND_LEN).await?;
+            let body_length = codec.read_async(Field::Body, bytes, &mut data).await?;
+            limits.check(Limit::Body, body_length)?;
+            read_more(bytes, &mut data, body_length).await?;
+        }
+
+        if let RequestKind::Item = (data[REQUEST_RESPONSE_KIND_OFFSET] & REQUEST_KIND_MASK).into() {
+            let name_length = codec.read_async(Field::ItemName, bytes, &mut data).await?;
+            limits.check(Limit::ItemName, name_length)?;
+            read_more(bytes, &mut data, name_length).await?;
+            codec.read_async(Field::Offset, bytes, &mut data).await?;
+            codec.read_async(Field::Item, bytes, &mut data).await?;
+        }
+
+        if flags & REQUEST_FLAG_CACHED != 0 {
+            read_more(bytes, &mut data, CONTENT_HASH_LEN).await?;
+        }
+
+        if flags & REQUEST_FLAG_KNOWN_ITEMS != 0 {
+            let count = codec
+                .read_async(Field::KnownItems, bytes, &mut data)
+                .await?;
+            limits.check(Limit::KnownItems, count)?;
+
+            for _ in 0..count {
+                read_more(bytes, &mut data, CONTENT_HASH_LEN).await?;
+            }
+        }
+
+        if flags & REQUEST_FLAG_EXTENSIONS != 0 {
+            read_more(bytes, &mut data, EXTENSION_COUNT_LEN).await?;
+            let count_offset = data.len() - EXTENSION_COUNT_LEN;
+
+            let mut extensions_len = 0;
+            for _ in 0..data[count_offset] {
+                read_more(bytes, &mut data, EXTENSION_HEADER_LEN).await?;
+                let header_offset = data.len() - EXTENSION_HEADER_LEN;
+
+                let value_len =
+                    crate::deserialize_bytes(&data[header_offset + EXTENSION_KEY_LEN..])?;
+                extensions_len += value_len;
+                limits.check(Limit::Extensions, extensions_len)?;
+                read_more(bytes, &mut data, value_len).await?;
+            }
+        }
+
+        Self::from_bytes_with_limits(&mut data.as_slice(), limits)
+    }
+
+    /// Write the request to an asynchronous sink of bytes.
+    #[cfg(feature = "async")]
+    pub async fn write_to_async(
+        &self,
+        writer: &mut (impl AsyncWrite + Unpin),
+    ) -> Result<(), FroggiError> {
+        writer.write_all(&self.bytes()).await?;
+        Ok(())
+    }
+
     /// Get the version of this request
     pub fn version(&self) -> u8 {
         self.version
@@ -127,31 +575,287 @@ impl Request {
         &self.request
     }
 
-    /// Convert the request into bytes
-    pub fn bytes(&self) -> Vec<u8> {
+    /// Get the path of the page, which is the request string up to any `#`
+    pub fn path(&self) -> &str {
+        self.request
+            .split_once('#')
+            .map_or(self.request.as_str(), |(path, _)| path)
+    }
+
+    /// Get the anchor after the `#` in the request string, for requests for part of a page
+    pub fn fragment(&self) -> Option<&str> {
+        self.request.split_once('#').map(|(_, fragment)| fragment)
+    }
+
+    /// Get the kind of data in the body, for put requests
+    pub fn body_kind(&self) -> ItemKind {
+        self.body_kind
+    }
+
+    /// Get the body of the request, which is empty unless this is a put request
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Get the name of the item asked for, which is empty unless this is an item request
+    pub fn item_name(&self) -> &str {
+        &self.item_name
+    }
+
+    /// Get the offset of the part of the item asked for
+    pub fn item_offset(&self) -> usize {
+        self.item_offset
+    }
+
+    /// Get the length of the part of the item asked for, or `None` for the rest of the item
+    pub fn item_len(&self) -> Option<usize> {
+        self.item_len
+    }
+
+    /// Get the content hash of the client's cached copy of the page, if it has one
+    pub fn cached_hash(&self) -> Option<ContentHash> {
+        self.cached
+    }
+
+    /// Get the hashes of the items the client already has
+    pub fn known_items(&self) -> &[ContentHash] {
+        &self.known_items
+    }
+
+    /// True if the client wants to send another request on the same connection
+    pub fn keep_alive(&self) -> bool {
+        self.keep_alive
+    }
+
+    /// Get every extension, including ones we don't know the meaning of
+    pub fn extensions(&self) -> &[Extension] {
+        &self.extensions
+    }
+
+    /// Get the value of the extension with this key
+    pub fn extension(&self, key: impl Into<u8>) -> Option<&[u8]> {
+        let key = key.into();
+        self.extensions
+            .iter()
+            .find(|extension| extension.key() == key)
+            .map(Extension::value)
+    }
+
+    /// Get the name and version of the client, if it sent one
+    pub fn user_agent(&self) -> Option<&str> {
+        self.extension(ExtensionKey::UserAgent)
+            .and_then(|value| std::str::from_utf8(value).ok())
+    }
+
+    /// Get the languages the client would like pages in, if it sent any
+    pub fn language(&self) -> Option<&str> {
+        self.extension(ExtensionKey::Language)
+            .and_then(|value| std::str::from_utf8(value).ok())
+    }
+
+    /// Get the kinds of items the client can display, if it said
+    pub fn accepted_items(&self) -> Option<Vec<ItemKind>> {
+        self.extension(ExtensionKey::AcceptItems)
+            .map(|value| value.iter().map(|&kind| kind.into()).collect())
+    }
+
+    /// Get the compression codecs the client can decompress, most preferred first
+    pub fn accepted_compression(&self) -> Vec<Compression> {
+        self.extension(ExtensionKey::AcceptCompression)
+            .map(|value| value.iter().map(|&codec| codec.into()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Get the width and height of the client's screen in pixels, if it sent them
+    pub fn screen_size(&self) -> Option<(u16, u16)> {
+        match self.extension(ExtensionKey::ScreenSize)? {
+            &[w0, w1, h0, h1] => Some((u16::from_le_bytes([w0, w1]), u16::from_le_bytes([h0, h1]))),
+            _ => None,
+        }
+    }
+
+    /// Get the number of bytes the request takes up when written
+    pub fn encoded_len(&self) -> usize {
+        let codec = self.codec();
+
+        let body_len = match self.kind {
+            RequestKind::Put => {
+                REQUEST_BODY_KIND_LEN + codec.len_of(Field::Body, self.body.len()) + self.body.len()
+            }
+            RequestKind::Item => {
+                codec.len_of(Field::ItemName, self.item_name.len())
+                    + self.item_name.len()
+                    + codec.len_of(Field::Offset, self.item_offset)
+                    + codec.len_of(Field::Item, self.item_len.unwrap_or_default())
+            }
+            _ => 0,
+        };
+
+        let cached_len = match self.cached {
+            Some(_) => CONTENT_HASH_LEN,
+            None => 0,
+        };
+
+        let known_items_len = if self.known_items.is_empty() {
+            0
+        } else {
+            codec.len_of(Field::KnownItems, self.known_items.len())
+                + self.known_items.len() * CONTENT_HASH_LEN
+        };
+
+        let extensions_len = if self.extensions.is_empty() {
+            0
+        } else {
+            EXTENSION_COUNT_LEN
+                + self
+                    .extensions
+                    .iter()
+                    .map(|extension| EXTENSION_HEADER_LEN + extension.value().len())
+                    .sum::<usize>()
+        };
+
+        FROGGI_HEADER_LEN
+            + codec.len_of(Field::Path, self.request.len())
+            + self.request.len()
+            + body_len
+            + cached_len
+            + known_items_len
+            + extensions_len
+    }
+
+    // which optional sections the request has
+    fn flags(&self) -> u8 {
+        let mut flags = 0;
+
+        if self.cached.is_some() {
+            flags |= REQUEST_FLAG_CACHED;
+        }
+
+        if !self.known_items.is_empty() {
+            flags |= REQUEST_FLAG_KNOWN_ITEMS;
+        }
+
+        if self.keep_alive {
+            flags |= REQUEST_FLAG_KEEP_ALIVE;
+        }
+
+        if !self.extensions.is_empty() {
+            flags |= REQUEST_FLAG_EXTENSIONS;
+        }
+
+        flags
+    }
+
+    /// Write the request to a sink of bytes.
+    pub fn write_to(&self, writer: &mut impl Write) -> Result<(), FroggiError> {
+        let codec = self.codec();
+        let mut header = Vec::with_capacity(FROGGI_HEADER_LEN + VARINT_MAX_LEN);
+        header.extend_from_slice(&FROGGI_MAGIC);
+
         // first byte is version
-        let mut data = Vec::from(FROGGI_MAGIC);
-        data.push(self.version);
+        header.push(self.version);
 
-        // second byte is request kind
-        data.push(self.kind.into());
+        // second byte is request kind and flags
+        header.push(Into::<u8>::into(self.kind) | self.flags());
 
         // next 16 bytes are client ID
-        data.extend(self.id.as_bytes());
+        header.extend_from_slice(self.id.as_bytes());
+
+        // next is the request length
+        codec.write(Field::Path, self.request.len(), &mut header)?;
+        writer.write_all(&header)?;
+
+        // next is the path
+        writer.write_all(self.request.as_bytes())?;
+
+        // put requests end with the body kind, length, and the body itself
+        if let RequestKind::Put = self.kind {
+            let mut body_header = Vec::with_capacity(REQUEST_BODY_KIND_LEN + VARINT_MAX_LEN);
+            body_header.push(self.body_kind.into());
+            codec.write(Field::Body, self.body.len(), &mut body_header)?;
+            writer.write_all(&body_header)?;
+            writer.write_all(&self.body)?;
+        }
+
+        // item requests end with the item name and the range of it to send
+        if let RequestKind::Item = self.kind {
+            let mut item = Vec::with_capacity(self.item_name.len() + 3 * VARINT_MAX_LEN);
+            codec.write(Field::ItemName, self.item_name.len(), &mut item)?;
+            item.extend_from_slice(self.item_name.as_bytes());
+            codec.write(Field::Offset, self.item_offset, &mut item)?;
+            codec.write(Field::Item, self.item_len.unwrap_or_default(), &mut item)?;
+            writer.write_all(&item)?;
+        }
+
+        // then the hash of the cached copy
+        if let Some(hash) = self.cached {
+            writer.write_all(hash.as_bytes())?;
+        }
 
-        // next two bytes are request length
-        // unwrap safety - we check the path length before request construction
-        let (low, high) = serialize_to_bytes(self.request.len()).unwrap();
-        data.push(low);
-        data.push(high);
+        // then the hashes of known items
+        if !self.known_items.is_empty() {
+            let mut count = Vec::with_capacity(VARINT_MAX_LEN);
+            codec.write(Field::KnownItems, self.known_items.len(), &mut count)?;
+            writer.write_all(&count)?;
 
-        // remainder of request is the path
-        data.extend(self.request.bytes());
+            for hash in self.known_items.iter() {
+                writer.write_all(hash.as_bytes())?;
+            }
+        }
+
+        // and the extensions come last
+        if !self.extensions.is_empty() {
+            // overflow safety - we check the number of extensions when adding them
+            let mut block = vec![self.extensions.len() as u8];
+            for extension in self.extensions.iter() {
+                block.push(extension.key());
+                // unwrap safety - we check the value length when creating the extension
+                let (low, high) = serialize_to_bytes(extension.value().len()).unwrap();
+                block.push(low);
+                block.push(high);
+                block.extend_from_slice(extension.value());
+            }
+            writer.write_all(&block)?;
+        }
+
+        Ok(())
+    }
+
+    /// Convert the request into bytes
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.encoded_len());
+
+        // unwrap safety - writing to a Vec never fails
+        self.write_to(&mut data).unwrap();
 
         data
     }
 }
 
+// read some more of a request onto the end of what's been read so far
+#[cfg(feature = "async")]
+async fn read_more(
+    bytes: &mut (impl AsyncRead + Unpin),
+    data: &mut Vec<u8>,
+    len: usize,
+) -> Result<(), FroggiError> {
+    let offset = data.len();
+    data.resize(offset + len, 0);
+    bytes.read_exact(&mut data[offset..]).await?;
+    Ok(())
+}
+
+// get the flags from a request kind byte, making sure we know what they all mean
+fn check_flags(kind: u8) -> Result<u8, FroggiError> {
+    let flags = kind & !REQUEST_KIND_MASK;
+    if flags & !REQUEST_FLAGS != 0 {
+        return Err(FroggiError::new(ErrorKind::RequestFormatError)
+            .msg(format!("Unknown request flags {:#04x}.", flags)));
+    }
+
+    Ok(flags)
+}
+
 impl Into<Vec<u8>> for Request {
     fn into(self) -> Vec<u8> {
         self.bytes()
@@ -172,21 +876,583 @@ mod test {
         0x69, 0x6e, 0x64, 0x65, 0x78, 0x2e, 0x66, 0x6d, 0x6c,       // request path
     ];
 
+    #[rustfmt::skip]
+    const PUT_BYTES: &[u8] = &[
+        0xf0, 0x9f, 0x90, 0xb8,                                     // froggi magic 🐸
+        0x00,                                                       // version
+        0x0e,                                                       // kind
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,  // UUID
+        0x04, 0x00,                                                 // request length
+        0x65, 0x63, 0x68, 0x6f,                                     // request path
+        0x00,                                                       // body kind
+        0x03, 0x00, 0x00, 0x00,                                     // body length
+        0x66, 0x72, 0x67,                                           // body
+    ];
+
+    #[rustfmt::skip]
+    const EXTENSION_BYTES: &[u8] = &[
+        0xf0, 0x9f, 0x90, 0xb8,                                     // froggi magic 🐸
+        0x00,                                                       // version
+        0x80,                                                       // kind, with extensions
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,  // UUID
+        0x09, 0x00,                                                 // request length
+        0x69, 0x6e, 0x64, 0x65, 0x78, 0x2e, 0x66, 0x6d, 0x6c,       // request path
+        0x03,                                                       // number of extensions
+        0x00,                                                       // user agent
+        0x07, 0x00,                                                 // length
+        0x66, 0x72, 0x67, 0x2f, 0x30, 0x2e, 0x31,                   // frg/0.1
+        0x03,                                                       // screen size
+        0x04, 0x00,                                                 // length
+        0x80, 0x02, 0xe0, 0x01,                                     // 640x480
+        0x7f,                                                       // some key from the future
+        0x01, 0x00,                                                 // length
+        0x3f,                                                       // ?
+    ];
+
     #[test]
     fn from_bytes() {
         let mut bytes = REQUEST_BYTES.clone();
         let request = Request::from_bytes(&mut bytes).unwrap();
-        assert_eq!(request.version, crate::FROGGI_VERSION);
+        assert_eq!(request.version, 0);
         assert_eq!(&request.request, "index.fml");
     }
 
     #[test]
     fn to_bytes() {
-        let request = Request::new("index.fml", RequestKind::PageOnly).unwrap();
+        let request = Request::new("index.fml", RequestKind::PageOnly)
+            .unwrap()
+            .with_version(0)
+            .unwrap();
         let data_test = request.bytes();
 
         assert_eq!(data_test.len(), REQUEST_BYTES.len());
 
         crate::test::test_bytes(REQUEST_BYTES, &data_test).unwrap();
     }
+
+    #[test]
+    fn version_1() {
+        // the same as version 0, with a one byte path length
+        let request = Request::new("index.fml", RequestKind::PageOnly).unwrap();
+        assert_eq!(request.version(), 0);
+        let request = request.with_version(1).unwrap();
+
+        let mut expected = REQUEST_BYTES.to_vec();
+        expected[FROGGI_VERSION_OFFSET] = 1;
+        expected.remove(REQUEST_LENGTH_OFFSET + 1);
+        assert_eq!(request.bytes(), expected);
+
+        // paths, bodies and known items can be larger than version 0 allows
+        let path = "a".repeat(u16::MAX as usize + 1);
+        let known = vec![ContentHash::of(b"frog"); u16::MAX as usize + 1];
+        let request = Request::new_put(&path, Uuid::nil(), ItemKind::Image, b"frg".to_vec())
+            .unwrap()
+            .with_known_items(known.clone())
+            .unwrap();
+        assert!(Request::new(&path, RequestKind::PageOnly)
+            .unwrap()
+            .with_version(0)
+            .is_err());
+
+        let bytes = request.bytes();
+        assert_eq!(bytes.len(), request.encoded_len());
+
+        let limits = DecodeLimits {
+            max_known_items: known.len(),
+            ..DecodeLimits::default()
+        };
+        let request = Request::from_bytes_with_limits(&mut bytes.as_slice(), limits).unwrap();
+        assert_eq!(request.request(), path);
+        assert_eq!(request.body(), b"frg");
+        assert_eq!(request.known_items(), &known[..]);
+
+        // the path length limit applies to every version
+        let limits = DecodeLimits {
+            max_path: 8,
+            ..DecodeLimits::default()
+        };
+        assert!(matches!(
+            Request::from_bytes_with_limits(&mut &REQUEST_BYTES[..], limits)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::LimitError {
+                limit: Limit::Path,
+                got: 9,
+                max: 8
+            }
+        ));
+
+        assert!(Request::new("index.fml", RequestKind::PageOnly)
+            .unwrap()
+            .with_version(2)
+            .is_err());
+    }
+
+    #[test]
+    fn unknown_kind() {
+        let mut bytes = REQUEST_BYTES.to_vec();
+        bytes[REQUEST_RESPONSE_KIND_OFFSET] = 0x07;
+
+        // kinds we don't know about are kept, so they're written back the same
+        let request = Request::from_bytes(&mut bytes.as_slice()).unwrap();
+        assert_eq!(request.kind(), RequestKind::Unknown(7));
+        assert_eq!(request.bytes(), bytes);
+
+        // kinds that run into the flags can't be written at all
+        for kind in &[0x13, 0x24, 0x41, 0x8f] {
+            assert!(matches!(
+                Request::new("a", RequestKind::Unknown(*kind))
+                    .unwrap_err()
+                    .kind(),
+                ErrorKind::RequestFormatError
+            ));
+        }
+        assert!(Request::new("a", RequestKind::Unknown(0x0f)).is_ok());
+    }
+
+    #[test]
+    fn put() {
+        let request = Request::new_put("echo", Uuid::nil(), ItemKind::Image, b"frg".to_vec())
+            .unwrap()
+            .with_version(0)
+            .unwrap();
+        let data_test = request.bytes();
+
+        assert_eq!(data_test.len(), request.encoded_len());
+        crate::test::test_bytes(PUT_BYTES, &data_test).unwrap();
+
+        let mut bytes = PUT_BYTES;
+        let request = Request::from_bytes(&mut bytes).unwrap();
+        assert!(matches!(request.kind(), RequestKind::Put));
+        assert!(matches!(request.body_kind(), ItemKind::Image));
+        assert_eq!(request.request(), "echo");
+        assert_eq!(request.body(), b"frg");
+        assert!(bytes.is_empty());
+
+        // the body is only there for put requests
+        let request = Request::new("echo", RequestKind::PageOnly).unwrap();
+        assert!(request.body().is_empty());
+        assert_eq!(request.bytes().len(), FROGGI_HEADER_LEN + 2 + 4);
+    }
+
+    #[test]
+    fn item() {
+        let request = Request::new_item("index.fml", Uuid::nil(), "big.png", 300, None)
+            .unwrap()
+            .with_version(0)
+            .unwrap();
+
+        // the same as a page request, with the item name and range after the path
+        let mut expected = REQUEST_BYTES.to_vec();
+        expected[REQUEST_RESPONSE_KIND_OFFSET] = 0x04;
+        expected.push(7);
+        expected.extend_from_slice(b"big.png");
+        expected.extend_from_slice(&[0x2c, 0x01, 0x00, 0x00]);
+        expected.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]);
+
+        let bytes = request.bytes();
+        assert_eq!(bytes.len(), request.encoded_len());
+        crate::test::test_bytes(&expected, &bytes).unwrap();
+
+        let request = Request::from_bytes(&mut bytes.as_slice()).unwrap();
+        assert_eq!(request.kind(), RequestKind::Item);
+        assert_eq!(request.request(), "index.fml");
+        assert_eq!(request.item_name(), "big.png");
+        assert_eq!(request.item_offset(), 300);
+        assert_eq!(request.item_len(), None);
+
+        let request =
+            Request::new_item("index.fml", Uuid::nil(), "big.png", 0, Some(1024)).unwrap();
+        let request = Request::from_bytes(&mut request.bytes().as_slice()).unwrap();
+        assert_eq!(request.item_len(), Some(1024));
+
+        // asking for nothing is a mistake, and names have a limit of their own
+        assert!(Request::new_item("index.fml", Uuid::nil(), "big.png", 0, Some(0)).is_err());
+        let limits = DecodeLimits {
+            max_item_name: 9,
+            ..DecodeLimits::default()
+        };
+        let request = Request::new_item("index.fml", Uuid::nil(), "a".repeat(10), 0, None).unwrap();
+        assert!(matches!(
+            Request::from_bytes_with_limits(&mut request.bytes().as_slice(), limits)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::LimitError {
+                limit: Limit::ItemName,
+                got: 10,
+                max: 9
+            }
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let request = Request::new_put(
+            "form",
+            Uuid::new_v4(),
+            ItemKind::Unknown(15),
+            b"frog".to_vec(),
+        )
+        .unwrap()
+        .with_cached_hash(ContentHash::of(b"page"))
+        .with_keep_alive()
+        .with_user_agent("frg/0.1")
+        .unwrap();
+
+        // bytes are written as base64
+        let mut json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["kind"], "Put");
+        assert_eq!(json["body"], "ZnJvZw==");
+        assert_eq!(json["extensions"][0]["value"], "ZnJnLzAuMQ==");
+
+        let round_trip: Request = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(round_trip.bytes(), request.bytes());
+
+        // and requests are checked on the way back in
+        json["version"] = 0.into();
+        json["request"] = "a".repeat(u16::MAX as usize + 1).into();
+        assert!(serde_json::from_value::<Request>(json).is_err());
+    }
+
+    #[test]
+    fn extensions() {
+        let request = Request::new("index.fml", RequestKind::PageOnly)
+            .unwrap()
+            .with_version(0)
+            .unwrap()
+            .with_user_agent("frg/0.1")
+            .unwrap()
+            .with_screen_size(640, 480)
+            .unwrap()
+            .with_extension(0x7f, b"?".to_vec())
+            .unwrap();
+        let data_test = request.bytes();
+
+        assert_eq!(data_test.len(), request.encoded_len());
+        assert_eq!(data_test.len(), EXTENSION_BYTES.len());
+        crate::test::test_bytes(EXTENSION_BYTES, &data_test).unwrap();
+
+        let mut bytes = EXTENSION_BYTES;
+        let request = Request::from_bytes(&mut bytes).unwrap();
+        assert_eq!(request.request(), "index.fml");
+        assert_eq!(request.user_agent(), Some("frg/0.1"));
+        assert_eq!(request.screen_size(), Some((640, 480)));
+        assert_eq!(request.language(), None);
+        assert!(request.accepted_items().is_none());
+        assert!(bytes.is_empty());
+
+        // keys we don't know about are kept, but they don't mean anything
+        assert_eq!(request.extensions().len(), 3);
+        assert_eq!(request.extension(0x7f), Some(&b"?"[..]));
+        assert!(matches!(
+            request.extensions()[2].known_key(),
+            ExtensionKey::Unknown(0x7f)
+        ));
+
+        // adding a key again replaces it
+        let request = request.with_user_agent("frg/0.2").unwrap();
+        assert_eq!(request.extensions().len(), 3);
+        assert_eq!(request.user_agent(), Some("frg/0.2"));
+
+        let request = request
+            .with_accepted_items(&[ItemKind::Image])
+            .unwrap()
+            .with_language("en-US, fr")
+            .unwrap();
+        let request = Request::from_bytes(&mut request.bytes().as_slice()).unwrap();
+        assert!(matches!(
+            request.accepted_items().as_deref(),
+            Some(&[ItemKind::Image])
+        ));
+        assert_eq!(request.language(), Some("en-US, fr"));
+    }
+
+    #[test]
+    fn cached_hash() {
+        let hash = ContentHash::of(b"index.fml");
+        let request = Request::new_put("echo", Uuid::nil(), ItemKind::Image, b"frg".to_vec())
+            .unwrap()
+            .with_version(0)
+            .unwrap()
+            .with_cached_hash(hash);
+
+        // same as a put request, with a flag in the kind byte and the hash at the end
+        let mut expected = PUT_BYTES.to_vec();
+        expected[REQUEST_RESPONSE_KIND_OFFSET] |= REQUEST_FLAG_CACHED;
+        expected.extend_from_slice(hash.as_bytes());
+
+        let bytes = request.bytes();
+        assert_eq!(bytes.len(), request.encoded_len());
+        crate::test::test_bytes(&expected, &bytes).unwrap();
+
+        let request = Request::from_bytes(&mut bytes.as_slice()).unwrap();
+        assert!(matches!(request.kind(), RequestKind::Put));
+        assert_eq!(request.body(), b"frg");
+        assert_eq!(request.cached_hash(), Some(hash));
+
+        // no hash, no flag
+        let mut bytes = REQUEST_BYTES;
+        assert_eq!(Request::from_bytes(&mut bytes).unwrap().cached_hash(), None);
+
+        // a flag without its section
+        let mut bytes = REQUEST_BYTES.to_vec();
+        bytes[REQUEST_RESPONSE_KIND_OFFSET] = REQUEST_FLAG_CACHED;
+        assert!(matches!(
+            Request::from_bytes(&mut bytes.as_slice())
+                .unwrap_err()
+                .kind(),
+            ErrorKind::IOError { .. }
+        ));
+    }
+
+    #[test]
+    fn known_items() {
+        let known = vec![
+            ContentHash::of(b"white.png"),
+            ContentHash::of(b"magenta.png"),
+        ];
+        let request = Request::new("index.fml", RequestKind::PageItems)
+            .unwrap()
+            .with_cached_hash(ContentHash::of(b"index.fml"))
+            .with_known_items(known.clone())
+            .unwrap();
+
+        let bytes = request.bytes();
+        assert_eq!(bytes.len(), request.encoded_len());
+        assert_eq!(bytes[REQUEST_RESPONSE_KIND_OFFSET], 0x31);
+
+        let request = Request::from_bytes(&mut bytes.as_slice()).unwrap();
+        assert!(matches!(request.kind(), RequestKind::PageItems));
+        assert_eq!(request.cached_hash(), Some(ContentHash::of(b"index.fml")));
+        assert_eq!(request.known_items(), &known[..]);
+        assert!(!request.keep_alive());
+
+        let bytes = request.with_keep_alive().bytes();
+        assert_eq!(bytes[REQUEST_RESPONSE_KIND_OFFSET], 0x71);
+        assert!(Request::from_bytes(&mut bytes.as_slice())
+            .unwrap()
+            .keep_alive());
+    }
+
+    #[test]
+    fn bad_header() {
+        let mut bytes = REQUEST_BYTES.to_vec();
+        bytes[0] = b'G';
+        assert!(matches!(
+            Request::from_bytes(&mut bytes.as_slice()).unwrap_err().kind(),
+            ErrorKind::MagicError { got } if got == b"G\x9f\x90\xb8"
+        ));
+
+        let mut bytes = REQUEST_BYTES.to_vec();
+        bytes[FROGGI_VERSION_OFFSET] = 0xfe;
+        assert!(matches!(
+            Request::from_bytes(&mut bytes.as_slice())
+                .unwrap_err()
+                .kind(),
+            ErrorKind::VersionError { got: 0xfe }
+        ));
+    }
+
+    #[test]
+    fn body_limit() {
+        let limits = DecodeLimits {
+            max_body: 2,
+            ..DecodeLimits::default()
+        };
+
+        let mut bytes = PUT_BYTES;
+        assert!(matches!(
+            Request::from_bytes_with_limits(&mut bytes, limits)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::LimitError {
+                limit: Limit::Body,
+                got: 3,
+                max: 2
+            }
+        ));
+
+        // a huge body length is refused before anything is allocated
+        let mut bytes = PUT_BYTES.to_vec();
+        let body_length = PUT_BYTES.len() - 3 - REQUEST_BODY_LENGTH_LEN;
+        bytes[body_length..body_length + REQUEST_BODY_LENGTH_LEN].copy_from_slice(&[0xff; 4]);
+        assert!(matches!(
+            Request::from_bytes(&mut bytes.as_slice())
+                .unwrap_err()
+                .kind(),
+            ErrorKind::LimitError {
+                limit: Limit::Body,
+                ..
+            }
+        ));
+    }
+
+    // a version 1 request claiming to know as many items as a length can count
+    fn huge_known_items() -> Vec<u8> {
+        let mut bytes = Request::new("index.fml", RequestKind::PageItems)
+            .unwrap()
+            .with_version(1)
+            .unwrap()
+            .with_known_items(vec![ContentHash::of(b"white.png")])
+            .unwrap()
+            .bytes();
+        assert_eq!(bytes[FROGGI_VERSION_OFFSET], 1);
+
+        let count = bytes.len() - CONTENT_HASH_LEN - 1;
+        assert_eq!(bytes[count], 1);
+        bytes.truncate(count);
+        bytes.extend_from_slice(&[0xff; 9]);
+        bytes.push(0x01);
+        bytes
+    }
+
+    #[test]
+    fn known_items_limit() {
+        let limits = DecodeLimits {
+            max_known_items: 1,
+            ..DecodeLimits::default()
+        };
+
+        let bytes = Request::new("index.fml", RequestKind::PageItems)
+            .unwrap()
+            .with_known_items(vec![
+                ContentHash::of(b"white.png"),
+                ContentHash::of(b"magenta.png"),
+            ])
+            .unwrap()
+            .bytes();
+        assert!(matches!(
+            Request::from_bytes_with_limits(&mut bytes.as_slice(), limits)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::LimitError {
+                limit: Limit::KnownItems,
+                got: 2,
+                max: 1
+            }
+        ));
+
+        // a huge count is refused before reading any hashes
+        assert!(matches!(
+            Request::from_bytes(&mut huge_known_items().as_slice())
+                .unwrap_err()
+                .kind(),
+            ErrorKind::LimitError {
+                limit: Limit::KnownItems,
+                ..
+            }
+        ));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_known_items_limit() {
+        assert!(matches!(
+            Request::from_bytes_async(&mut huge_known_items().as_slice())
+                .await
+                .unwrap_err()
+                .kind(),
+            ErrorKind::LimitError {
+                limit: Limit::KnownItems,
+                ..
+            }
+        ));
+    }
+
+    // a request with two extensions that are as long as they can be
+    fn long_extensions() -> Vec<u8> {
+        Request::new("index.fml", RequestKind::PageOnly)
+            .unwrap()
+            .with_extension(ExtensionKey::UserAgent, vec![b'f'; u16::MAX as usize])
+            .unwrap()
+            .with_extension(ExtensionKey::Language, vec![b'r'; u16::MAX as usize])
+            .unwrap()
+            .bytes()
+    }
+
+    #[test]
+    fn extensions_limit() {
+        assert!(matches!(
+            Request::from_bytes(&mut long_extensions().as_slice())
+                .unwrap_err()
+                .kind(),
+            ErrorKind::LimitError {
+                limit: Limit::Extensions,
+                got,
+                max,
+            } if *got == 2 * u16::MAX as usize && *max == 64 * 1024
+        ));
+
+        let limits = DecodeLimits {
+            max_extensions: 2 * u16::MAX as usize,
+            ..DecodeLimits::default()
+        };
+        let request =
+            Request::from_bytes_with_limits(&mut long_extensions().as_slice(), limits).unwrap();
+        assert_eq!(request.user_agent().unwrap().len(), u16::MAX as usize);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_extensions_limit() {
+        assert!(matches!(
+            Request::from_bytes_async(&mut long_extensions().as_slice())
+                .await
+                .unwrap_err()
+                .kind(),
+            ErrorKind::LimitError {
+                limit: Limit::Extensions,
+                ..
+            }
+        ));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_put() {
+        let mut bytes = PUT_BYTES;
+        let request = Request::from_bytes_async(&mut bytes).await.unwrap();
+        assert_eq!(request.body(), b"frg");
+        assert!(bytes.is_empty());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_item() {
+        let request = Request::new_item("index.fml", Uuid::nil(), "big.png", 300, Some(5)).unwrap();
+        let bytes = request.bytes();
+        let request = Request::from_bytes_async(&mut bytes.as_slice())
+            .await
+            .unwrap();
+        assert_eq!(request.item_name(), "big.png");
+        assert_eq!(request.item_len(), Some(5));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_extensions() {
+        let mut bytes = EXTENSION_BYTES;
+        let request = Request::from_bytes_async(&mut bytes).await.unwrap();
+        assert_eq!(request.screen_size(), Some((640, 480)));
+        assert!(bytes.is_empty());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_round_trip() {
+        let request = Request::new("index.fml", RequestKind::PageOnly)
+            .unwrap()
+            .with_version(0)
+            .unwrap();
+        let mut data_test = Vec::new();
+        request.write_to_async(&mut data_test).await.unwrap();
+
+        crate::test::test_bytes(REQUEST_BYTES, &data_test).unwrap();
+
+        let request = Request::from_bytes_async(&mut data_test.as_slice())
+            .await
+            .unwrap();
+        assert_eq!(request.request(), "index.fml");
+    }
 }
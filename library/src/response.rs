@@ -1,32 +1,196 @@
 //! Types for dealing with a froggi protocol response.
 
+use crate::codec::{Codec, Field};
+use crate::compression::{self, Compression};
+use crate::hash::{ContentHash, ContentHasher};
+use crate::limits::{DecodeLimits, Limit};
+use crate::request::{Request, RequestKind};
+use crate::store::ItemStore;
 use crate::{protocol::*, AddMsg, ErrorKind, FroggiError, Uuid};
+use froggi_derive::U8Enum;
 
 use crate::ErrorKind::ResponseFormatError;
+use std::borrow::Cow;
+use std::collections::HashSet;
 use std::convert::TryInto;
-use std::io::Read;
-
-// TODO proc macro
-crate::u8enum! { ResponseKind {
-    Page = 0,
-    PageNoItems = 1,
+use std::io::{self, Read, Write};
+
+#[cfg(feature = "async")]
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Kinds of responses.
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, U8Enum)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ResponseKind {
+    /// Plain old page. No items or additional page expressions will be sent.
+    PageNoItems = 0,
+    /// Page with items. No additional page expressions will be sent.
+    Page = 1,
+    /// Groups of page expressions to insert after anchors in a page the client already has.
+    AdditionalExpressions = 2,
+    /// What was asked for is somewhere else now. The page is its URL.
+    Redirect = 3,
+    /// The client's cached copy is still good, so there's no page or items.
+    NotModified = 4,
+    /// Part or all of a single item, with where it goes in the whole item.
+    ItemRange = 5,
+    /// Something went wrong. See the error code.
     Error = 14,
-    Unknown = 15,
-} }
+    /// A kind we don't know about.
+    Unknown(u8),
+}
+
+/// Why an error response is an error.
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, U8Enum)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ErrorCode {
+    /// The request didn't make sense.
+    BadRequest = 0,
+    /// There's nothing at that path.
+    NotFound = 1,
+    /// The server doesn't speak the request's froggi version.
+    UnsupportedVersion = 2,
+    /// The request was larger than the server is willing to read.
+    TooLarge = 3,
+    /// The client ID is unknown or has expired.
+    InvalidClientId = 4,
+    /// The item isn't long enough to have the part that was asked for.
+    BadRange = 5,
+    /// Something went wrong on the server's end.
+    ServerError = 14,
+    /// A code we don't know about. The spec reserves 15 for this.
+    Unknown(u8),
+}
 
-// TODO proc macro
-crate::u8enum! { ItemKind {
+/// Kinds of items.
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, U8Enum)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ItemKind {
+    /// An image. Up to the recipient to determine the format.
     Image = 0,
-    Unknown = 15,
-} }
+    /// Sound or music.
+    Audio = 1,
+    /// A moving picture, possibly with sound.
+    Video = 2,
+    /// Plain text, in utf8.
+    Text = 3,
+    /// A font the page can use.
+    Font = 4,
+    /// A stylesheet the page can use.
+    Stylesheet = 5,
+    /// Anything else, for the client to save rather than display.
+    Binary = 6,
+    /// A kind we don't know about. The spec reserves 15 for this.
+    Unknown(u8),
+}
+
+impl ItemKind {
+    /// Get the most general media type of the kind, for items that don't have a more specific
+    /// one.
+    pub fn media_type(self) -> &'static str {
+        match self {
+            ItemKind::Image => "image/*",
+            ItemKind::Audio => "audio/*",
+            ItemKind::Video => "video/*",
+            ItemKind::Text => "text/plain",
+            ItemKind::Font => "font/*",
+            ItemKind::Stylesheet => "text/css",
+            ItemKind::Binary | ItemKind::Unknown(_) => "application/octet-stream",
+        }
+    }
+
+    // images, sound and video are almost always compressed already, so compressing them again
+    // costs time and doesn't save anything
+    fn worth_compressing(self) -> bool {
+        !matches!(self, ItemKind::Image | ItemKind::Audio | ItemKind::Video)
+    }
+}
+
+/// Where the data of an item range response goes in the whole item.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ItemRange {
+    /// The offset of the data in the item
+    pub offset: usize,
+    /// The length of the whole item
+    pub size: usize,
+}
 
 /// An extra item that may appear at the end of a page.
+///
+/// Items the client already has may be elided by the server, leaving only their hash. Items in
+/// metadata responses are always elided, but still know how large their data is.
+///
+/// With serde, elided items are written without their data, the same as on the wire.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "ItemFields"))]
 pub struct Item {
     name: String,
     kind: ItemKind,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    media_type: Option<String>,
+    hash: ContentHash,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    elided: bool,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    size: Option<usize>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::compact", skip_serializing_if = "Vec::is_empty")
+    )]
+    data: Vec<u8>,
+}
+
+// the hash may be left out when writing items by hand. if it's there, it has to match
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct ItemFields {
+    name: String,
+    kind: ItemKind,
+    #[serde(default)]
+    media_type: Option<String>,
+    hash: Option<ContentHash>,
+    #[serde(default)]
+    size: Option<usize>,
+    #[serde(with = "crate::compact", default)]
     data: Vec<u8>,
 }
 
+#[cfg(feature = "serde")]
+impl std::convert::TryFrom<ItemFields> for Item {
+    type Error = FroggiError;
+
+    fn try_from(fields: ItemFields) -> Result<Item, FroggiError> {
+        let item = match (fields.hash, fields.size) {
+            (Some(hash), Some(size)) if fields.data.is_empty() => {
+                Item::described(fields.name, fields.kind, hash, size)
+            }
+            (Some(hash), None) if fields.data.is_empty() && hash != ContentHash::of(&[]) => {
+                Item::elided(fields.name, fields.kind, hash)
+            }
+            _ => {
+                let item = Item::new(fields.name, fields.kind, fields.data);
+                if matches!(fields.hash, Some(hash) if hash != item.hash) {
+                    return Err(FroggiError::new(ResponseFormatError).msg(format!(
+                        "The data of item {} doesn't match its hash.",
+                        item.name
+                    )));
+                }
+                item
+            }
+        };
+
+        Ok(Item {
+            media_type: fields.media_type,
+            ..item
+        })
+    }
+}
+
 impl std::fmt::Debug for Item {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Item {{ name: {}, data: ... }}", self.name)
@@ -36,7 +200,61 @@ impl std::fmt::Debug for Item {
 impl Item {
     /// Create a new item
     pub fn new(name: String, kind: ItemKind, data: Vec<u8>) -> Item {
-        Item { name, kind, data }
+        Item {
+            name,
+            kind,
+            media_type: None,
+            hash: ContentHash::of(&data),
+            elided: false,
+            size: None,
+            data,
+        }
+    }
+
+    /// Create an item, working out its kind and media type from its data and name.
+    pub fn sniffed(name: String, data: Vec<u8>) -> Item {
+        let (kind, media_type) = crate::media::sniff_named(&name, &data);
+        Item::new(name, kind, data).with_media_type(media_type)
+    }
+
+    /// Label the item with a media type, like `text/x-rust`, for formats that can't be sniffed
+    pub fn with_media_type(self, media_type: impl ToString) -> Item {
+        Item {
+            media_type: Some(media_type.to_string()),
+            ..self
+        }
+    }
+
+    /// Create an item whose data was left out, because the client already has it
+    pub fn elided(name: String, kind: ItemKind, hash: ContentHash) -> Item {
+        Item {
+            name,
+            kind,
+            media_type: None,
+            hash,
+            elided: true,
+            size: None,
+            data: Vec::new(),
+        }
+    }
+
+    /// Create an item for a metadata response, which has the size of its data but not the data
+    /// itself
+    pub fn described(name: String, kind: ItemKind, hash: ContentHash, size: usize) -> Item {
+        Item {
+            size: Some(size),
+            ..Item::elided(name, kind, hash)
+        }
+    }
+
+    // leave out the data, but remember how much of it there was
+    fn into_described(self) -> Item {
+        Item {
+            size: self.size(),
+            elided: true,
+            data: Vec::new(),
+            ..self
+        }
     }
 
     /// Get the name of the item
@@ -49,62 +267,148 @@ impl Item {
         self.kind
     }
 
-    /// Get the data of the item
+    /// Get the data of the item, which is empty if it was elided
     pub fn data(&self) -> &[u8] {
         &self.data
     }
+
+    /// Get the length of the item's data, if we know it.
+    ///
+    /// Elided items only know how large they are if they came from a metadata response.
+    pub fn size(&self) -> Option<usize> {
+        if self.elided {
+            self.size
+        } else {
+            Some(self.data.len())
+        }
+    }
+
+    /// Get the media type of the item, like `image/png`.
+    ///
+    /// Items that weren't given one have the kind's general media type.
+    pub fn media_type(&self) -> &str {
+        self.media_type
+            .as_deref()
+            .unwrap_or_else(|| self.kind.media_type())
+    }
+
+    /// True if the item was given a media type of its own
+    pub fn has_media_type(&self) -> bool {
+        self.media_type.is_some()
+    }
+
+    /// Get the content hash of the item's data
+    pub fn hash(&self) -> ContentHash {
+        self.hash
+    }
+
+    /// True if the item's data was left out
+    pub fn is_elided(&self) -> bool {
+        self.elided
+    }
 }
 
 /// Represents a response from a froggi server.
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "ResponseFields"))]
 pub struct Response {
     version: u8,
     kind: ResponseKind,
     id: Uuid,
+    code: Option<ErrorCode>,
+    page: String,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    page_len: Option<usize>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    range: Option<ItemRange>,
+    items: Vec<Item>,
+}
+
+// deserialized responses are built like any other
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct ResponseFields {
+    version: u8,
+    kind: ResponseKind,
+    id: Uuid,
+    code: Option<ErrorCode>,
     page: String,
+    #[serde(default)]
+    page_len: Option<usize>,
+    #[serde(default)]
+    range: Option<ItemRange>,
     items: Vec<Item>,
 }
 
-fn check_page_and_items(page: &str, items: &[Item]) -> Result<(), FroggiError> {
-    if items.len() > u8::MAX as usize {
+#[cfg(feature = "serde")]
+impl std::convert::TryFrom<ResponseFields> for Response {
+    type Error = FroggiError;
+
+    fn try_from(fields: ResponseFields) -> Result<Response, FroggiError> {
+        ResponseBuilder {
+            version: fields.version,
+            kind: Some(fields.kind),
+            id: Some(fields.id),
+            code: fields.code,
+            page: Some(fields.page),
+            metadata: fields.page_len.is_some(),
+            page_len: fields.page_len,
+            range: fields.range,
+            items: fields.items,
+            bad_anchor: None,
+        }
+        .build()
+    }
+}
+
+fn check_page_and_items(codec: Codec, response: &Response) -> Result<(), FroggiError> {
+    if codec.check(Field::Items, response.items.len()).is_err() {
         return Err(
             FroggiError::new(ErrorKind::ResponseFormatError).msg_str("There are too many items.")
         );
     }
 
-    for item in items.iter() {
-        if item.data.len() > u32::MAX as usize {
+    for item in response.items.iter() {
+        if codec
+            .check(Field::Item, item.size().unwrap_or_default())
+            .is_err()
+        {
             return Err(FroggiError::new(ErrorKind::ResponseFormatError)
                 .msg(format!("The item {} is too long.", item.name)));
         }
 
-        if item.name.len() > u8::MAX as usize {
+        if codec.check(Field::ItemName, item.name.len()).is_err() {
             return Err(FroggiError::new(ErrorKind::ResponseFormatError)
                 .msg(format!("The item name {} is too long.", item.name)));
         }
+
+        if codec
+            .check(Field::MediaType, item.media_type().len())
+            .is_err()
+        {
+            return Err(FroggiError::new(ErrorKind::ResponseFormatError)
+                .msg(format!("The media type of item {} is too long.", item.name)));
+        }
+    }
+
+    if let Some(range) = response.range {
+        if codec.check(Field::Offset, range.offset).is_err()
+            || codec.check(Field::Item, range.size).is_err()
+        {
+            return Err(FroggiError::new(ErrorKind::ResponseFormatError)
+                .msg_str("The item range is too large."));
+        }
     }
 
-    if page.len() > u32::MAX as usize {
+    if codec.check(Field::Page, response.page_len()).is_err() {
         return Err(
             FroggiError::new(ErrorKind::ResponseFormatError).msg_str("The page is too long.")
         );
     }
 
-    if FROGGI_HEADER_LEN
-        + items
-            .iter()
-            .map(|item| {
-                item.data.len()
-                    + item.name.len()
-                    + ITEM_KIND_LEN
-                    + ITEM_KIND_LEN
-                    + ITEM_NAME_LENGTH_LEN
-            })
-            .sum::<usize>()
-        + PAGE_LENGTH_LEN
-        + page.len()
-        > (u32::MAX as usize)
-    {
+    let total = Parts::plain(response, &HashSet::new()).total_len(codec, response.kind, true);
+    if codec.check(Field::Total, total).is_err() {
         return Err(
             FroggiError::new(ResponseFormatError).msg_str("The page and items are too large.")
         );
@@ -113,87 +417,327 @@ fn check_page_and_items(page: &str, items: &[Item]) -> Result<(), FroggiError> {
     Ok(())
 }
 
-impl Response {
-    /// Parse the response into a page. Zero-copy.
-    #[cfg(feature = "markup")]
-    pub fn parse(&self) -> Result<crate::markup::Page<'_>, Vec<FroggiError>> {
-        crate::markup::parse::parse(&self.page)
+// the page and items of a response as they go on the wire, with the data of items the client
+// already has left out, and compressed if that makes them smaller. metadata responses leave out
+// all of the data, but keep its length
+struct Parts<'a> {
+    compressed: bool,
+    metadata: bool,
+    media_types: bool,
+    range: Option<ItemRange>,
+    page: Part<'a>,
+    items: Vec<(&'a Item, Part<'a>)>,
+}
+
+// some data, how it was compressed, and the length to write before it
+struct Part<'a> {
+    compression: Compression,
+    len: usize,
+    data: Cow<'a, [u8]>,
+}
+
+impl<'a> Part<'a> {
+    fn plain(data: &'a [u8]) -> Self {
+        Part {
+            compression: Compression::None,
+            len: data.len(),
+            data: Cow::Borrowed(data),
+        }
     }
 
-    /// Read a response from a source of bytes.
-    pub fn from_bytes(bytes: &mut impl Read) -> Result<Self, FroggiError> {
-        // response header, 26 bytes long
-        let mut header = [0u8; PAGE_OFFSET];
-        bytes.read_exact(&mut header)?;
+    fn described(len: usize) -> Self {
+        Part {
+            compression: Compression::None,
+            len,
+            data: Cow::Borrowed(&[]),
+        }
+    }
+}
 
-        // version and kind are first two bytes
-        let version = header[FROGGI_VERSION_OFFSET];
-        let kind = header[REQUEST_RESPONSE_KIND_OFFSET].into();
+impl<'a> Parts<'a> {
+    // compressed parts are taken from `cached` if it was compressed with the same codec, and
+    // compressed on the spot otherwise
+    fn new(
+        response: &'a Response,
+        known: &HashSet<ContentHash>,
+        compression: Compression,
+        metadata: bool,
+        cached: Option<&'a Compressed>,
+    ) -> Result<Self, FroggiError> {
+        if metadata || response.is_metadata() {
+            return Ok(Parts {
+                compressed: false,
+                metadata: true,
+                media_types: response.has_media_types(),
+                range: response.range,
+                page: Part::described(response.page_len()),
+                items: response
+                    .items
+                    .iter()
+                    .map(|item| (item, Part::described(item.size().unwrap_or_default())))
+                    .collect(),
+            });
+        }
 
-        // next 16 is client ID
-        let id = Uuid::from_bytes(
-            header[REQUEST_RESPONSE_UUID_OFFSET..TOTAL_RESPONSE_LENGTH_OFFSET]
-                .try_into()
-                .unwrap(),
-        );
+        let cached = cached.filter(|cached| cached.compression == compression);
+        let part = |data: &'a [u8],
+                    worth_compressing: bool,
+                    cached: Option<&'a Option<Vec<u8>>>|
+         -> Result<Part<'a>, FroggiError> {
+            let compressed = match cached {
+                Some(cached) => cached.as_deref().map(Cow::Borrowed),
+                None => compress_part(compression, data, worth_compressing)?.map(Cow::Owned),
+            };
+
+            Ok(match compressed {
+                Some(compressed) => Part {
+                    compression,
+                    len: compressed.len(),
+                    data: compressed,
+                },
+                None => Part::plain(data),
+            })
+        };
 
-        // next four bytes is response length
-        let _total_response_length = crate::deserialize_four_bytes(
-            &header[TOTAL_RESPONSE_LENGTH_OFFSET..PAGE_LENGTH_OFFSET],
-        );
+        let items = response
+            .items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| {
+                if is_elided(item, known) {
+                    Ok((item, Part::plain(&[])))
+                } else {
+                    let cached = cached.map(|cached| &cached.items[i]);
+                    Ok((
+                        item,
+                        part(&item.data, item.kind.worth_compressing(), cached)?,
+                    ))
+                }
+            })
+            .collect::<Result<_, FroggiError>>()?;
+
+        Ok(Parts {
+            compressed: compression != Compression::None,
+            metadata: false,
+            media_types: response.has_media_types(),
+            range: response.range,
+            page: part(
+                response.page.as_bytes(),
+                true,
+                cached.map(|cached| &cached.page),
+            )?,
+            items,
+        })
+    }
 
-        // next four bytes is page length
-        let page_len = crate::deserialize_four_bytes(&header[PAGE_LENGTH_OFFSET..PAGE_OFFSET])?;
+    // unwrap safety - leaving things uncompressed never fails
+    fn plain(response: &'a Response, known: &HashSet<ContentHash>) -> Self {
+        Parts::new(response, known, Compression::None, false, None).unwrap()
+    }
 
-        // read page
-        let mut page_buf = vec![0; page_len];
-        bytes.read_exact(&mut page_buf)?;
-        let page = String::from_utf8(page_buf)?;
+    // the number of bytes the response takes up on the wire
+    fn total_len(&self, codec: Codec, kind: ResponseKind, hashes: bool) -> usize {
+        // lengths in version 1 are as long as they need to be, so the total can't be known until
+        // everything else is
+        FROGGI_HEADER_LEN
+            + codec.len_of(Field::Total, 0)
+            + kind_header_len(codec, kind, self.range)
+            + self.compression_len()
+            + codec.len_of(Field::Page, self.page.len)
+            + self.page.data.len()
+            + codec.len_of(Field::Items, self.items.len())
+            + self
+                .items
+                .iter()
+                .map(|(item, part)| {
+                    self.item_header_len(codec, item, part, hashes) + part.data.len()
+                })
+                .sum::<usize>()
+    }
+
+    fn item_header_len(&self, codec: Codec, item: &Item, part: &Part, hashes: bool) -> usize {
+        ITEM_KIND_LEN
+            + codec.len_of(Field::ItemName, item.name.len())
+            + item.name.len()
+            + self.media_type_len(codec, item)
+            + if hashes { CONTENT_HASH_LEN } else { 0 }
+            + self.compression_len()
+            + codec.len_of(Field::Item, part.len)
+    }
+
+    fn compression_len(&self) -> usize {
+        compression_len(self.compressed)
+    }
+
+    fn media_type_len(&self, codec: Codec, item: &Item) -> usize {
+        if self.media_types {
+            let len = item.media_type.as_ref().map_or(0, String::len);
+            codec.len_of(Field::MediaType, len) + len
+        } else {
+            0
+        }
+    }
+}
+
+// the compressed data of a page or item, or nothing if it isn't worth sending compressed
+fn compress_part(
+    compression: Compression,
+    data: &[u8],
+    worth_compressing: bool,
+) -> Result<Option<Vec<u8>>, FroggiError> {
+    if worth_compressing {
+        crate::compression::compress(compression, data)
+    } else {
+        Ok(None)
+    }
+}
+
+// the page and items of a response compressed with one codec, ahead of time
+#[derive(Debug)]
+struct Compressed {
+    compression: Compression,
+    page: Option<Vec<u8>>,
+    items: Vec<Option<Vec<u8>>>,
+}
+
+impl Compressed {
+    fn new(response: &Response, compression: Compression) -> Result<Self, FroggiError> {
+        Ok(Compressed {
+            compression,
+            page: compress_part(compression, response.page.as_bytes(), true)?,
+            items: response
+                .items
+                .iter()
+                .map(|item| compress_part(compression, &item.data, item.kind.worth_compressing()))
+                .collect::<Result<_, FroggiError>>()?,
+        })
+    }
+}
+
+fn is_elided(item: &Item, known: &HashSet<ContentHash>) -> bool {
+    item.elided || known.contains(&item.hash)
+}
+
+// compressed responses have a codec before the page length and each item length
+fn read_compression(compressed: bool, reader: &mut impl Read) -> Result<Compression, FroggiError> {
+    if compressed {
+        let mut compression = [0u8; COMPRESSION_LEN];
+        reader.read_exact(&mut compression)?;
+        Ok(compression[0].into())
+    } else {
+        Ok(Compression::None)
+    }
+}
 
-        // number of items, one byte
-        let mut num_items = [0u8; NUM_ITEMS_LEN];
-        bytes.read_exact(&mut num_items)?;
-        let num_items = num_items[0] as usize;
+fn compression_len(compressed: bool) -> usize {
+    if compressed {
+        COMPRESSION_LEN
+    } else {
+        0
+    }
+}
 
-        // read items
-        let mut items = Vec::with_capacity(num_items);
-        for _ in 0..num_items {
-            // item kind, 1 byte
-            let mut item_kind = [0u8; ITEM_KIND_LEN];
-            bytes.read_exact(&mut item_kind)?;
-            let kind = item_kind[0].into();
+// add up lengths read from the peer. ones too large to add up can't fit in the total length
+// either, so they come out as the largest length there is
+fn sum_lens(lens: &[usize]) -> usize {
+    lens.iter()
+        .try_fold(0usize, |sum, &len| sum.checked_add(len))
+        .unwrap_or(usize::MAX)
+}
 
-            // length of the item's name, 1 byte
-            let mut item_name_len = [0u8; ITEM_NAME_LENGTH_LEN];
-            bytes.read_exact(&mut item_name_len)?;
-            let item_name_len = item_name_len[0] as usize;
+// error responses have a code between the total length and the page length, and item range
+// responses have the offset of the range and the length of the whole item
+fn kind_header_len(codec: Codec, kind: ResponseKind, range: Option<ItemRange>) -> usize {
+    match (kind, range) {
+        (ResponseKind::Error, _) => ERROR_CODE_LEN,
+        (ResponseKind::ItemRange, Some(range)) => {
+            codec.len_of(Field::Offset, range.offset) + codec.len_of(Field::Item, range.size)
+        }
+        _ => 0,
+    }
+}
 
-            // item name
-            let mut name_buf = vec![0; item_name_len];
-            bytes.read_exact(&mut name_buf)?;
-            let name = String::from_utf8(name_buf)?;
+impl Response {
+    /// Parse the response into a page. Zero-copy.
+    #[cfg(feature = "markup")]
+    pub fn parse(&self) -> Result<crate::markup::Page<'_>, Vec<FroggiError>> {
+        crate::markup::parse::parse(&self.page)
+    }
 
-            // item length, 4 bytes
-            let mut item_len = [0u8; ITEM_LENGTH_LEN];
-            bytes.read_exact(&mut item_len)?;
-            let item_len = crate::deserialize_four_bytes(&item_len)?;
+    /// Read a response from a source of bytes, with the default limits.
+    pub fn from_bytes(bytes: &mut impl Read) -> Result<Self, FroggiError> {
+        Self::from_bytes_with_limits(bytes, DecodeLimits::default())
+    }
 
-            // item
-            let mut data = vec![0; item_len];
-            bytes.read_exact(&mut data)?;
+    /// Read a response from a source of bytes, refusing to read anything larger than the limits.
+    pub fn from_bytes_with_limits(
+        bytes: &mut impl Read,
+        limits: DecodeLimits,
+    ) -> Result<Self, FroggiError> {
+        let mut reader = ResponseReader::with_limits(bytes, limits)?;
 
-            items.push(Item { name, kind, data });
+        let mut items = Vec::with_capacity(reader.items_left());
+        while let Some(item) = reader.next_item()? {
+            items.push(item.into_item()?);
         }
 
+        let page_len = reader.metadata.then_some(reader.page_len);
         Ok(Self {
-            version,
-            kind,
-            id,
-            page,
+            range: reader.range,
+            version: reader.version,
+            kind: reader.kind,
+            id: reader.id,
+            code: reader.code,
+            page: reader.page,
+            page_len,
             items,
         })
     }
 
+    /// Read a response from an asynchronous source of bytes, with the default limits.
+    #[cfg(feature = "async")]
+    pub async fn from_bytes_async(
+        bytes: &mut (impl AsyncRead + Unpin),
+    ) -> Result<Self, FroggiError> {
+        Self::from_bytes_async_with_limits(bytes, DecodeLimits::default()).await
+    }
+
+    /// Read a response from an asynchronous source of bytes, with limits.
+    #[cfg(feature = "async")]
+    pub async fn from_bytes_async_with_limits(
+        bytes: &mut (impl AsyncRead + Unpin),
+        limits: DecodeLimits,
+    ) -> Result<Self, FroggiError> {
+        // read the header for the total length, then decode the rest from memory
+        let mut data = vec![0; FROGGI_HEADER_LEN];
+        bytes.read_exact(&mut data).await?;
+        let codec = crate::check_header(&data)?;
+
+        let total_response_length = codec.read_async(Field::Total, bytes, &mut data).await?;
+        limits.check(Limit::Total, total_response_length)?;
+
+        if total_response_length < data.len() {
+            return Err(FroggiError::new(ResponseFormatError)
+                .msg_str("The total response length is too short."));
+        }
+
+        let read = data.len();
+        data.resize(total_response_length, 0);
+        bytes.read_exact(&mut data[read..]).await?;
+
+        Self::from_bytes_with_limits(&mut data.as_slice(), limits)
+    }
+
+    /// Write the response to an asynchronous sink of bytes.
+    #[cfg(feature = "async")]
+    pub async fn write_to_async(
+        &self,
+        writer: &mut (impl AsyncWrite + Unpin),
+    ) -> Result<(), FroggiError> {
+        writer.write_all(&self.bytes()).await?;
+        Ok(())
+    }
+
     /// Get the version of the response
     pub fn version(&self) -> u8 {
         self.version
@@ -209,79 +753,325 @@ impl Response {
         self.id
     }
 
-    /// Get the page of the response, un-parsed
+    /// Get the error code, if this is an error response
+    pub fn error_code(&self) -> Option<ErrorCode> {
+        self.code
+    }
+
+    /// Get where the item goes in the whole item, if this is an item range response
+    pub fn item_range(&self) -> Option<ItemRange> {
+        self.range
+    }
+
+    /// Hash the kind, page, and items of the response.
+    ///
+    /// Clients send this back in requests for the same page, so the server can tell whether they
+    /// need it again. The version and client ID aren't part of the hash. Metadata responses don't
+    /// have a page to hash, so their hash doesn't match the page they describe.
+    pub fn content_hash(&self) -> ContentHash {
+        let mut hasher = ContentHasher::new();
+        hasher.update(&[self.kind.into()]);
+        hasher.update_with_len(self.page.as_bytes());
+        hasher.update(&(self.items.len() as u64).to_le_bytes());
+
+        for item in self.items.iter() {
+            hasher.update(&[item.kind.into()]);
+            hasher.update_with_len(item.name.as_bytes());
+            hasher.update(item.hash.as_bytes());
+        }
+
+        hasher.finish()
+    }
+
+    /// Get the URL this response redirects to, if it's a redirect
+    pub fn redirect_target(&self) -> Option<&str> {
+        match self.kind {
+            ResponseKind::Redirect => Some(&self.page),
+            _ => None,
+        }
+    }
+
+    /// Turn an error response into a `StatusError`, and pass anything else through.
+    ///
+    /// The page of the error response is used as the error message.
+    pub fn error_for_status(self) -> Result<Response, FroggiError> {
+        match self.code {
+            Some(code) => Err(FroggiError::new(ErrorKind::StatusError { code }).msg(self.page)),
+            None => Ok(self),
+        }
+    }
+
+    /// Get the page of the response, un-parsed. Empty in metadata responses.
     pub fn page(&self) -> &str {
         &self.page
     }
 
+    /// Get the length of the page, even if it was left out of a metadata response
+    pub fn page_len(&self) -> usize {
+        self.page_len.unwrap_or(self.page.len())
+    }
+
+    /// True if this is a metadata response, which only describes the page and items
+    pub fn is_metadata(&self) -> bool {
+        self.page_len.is_some()
+    }
+
     /// Get the extra items of a page
     pub fn items(&self) -> &[Item] {
         &self.items
     }
 
-    /// Convert the page into bytes
-    pub fn bytes(&self) -> Vec<u8> {
-        let mut data = Vec::from(FROGGI_MAGIC);
+    /// Get the number of bytes the response takes up when written
+    pub fn encoded_len(&self) -> usize {
+        Parts::plain(self, &HashSet::new()).total_len(
+            self.codec(),
+            self.kind,
+            self.has_elided_items(),
+        )
+    }
 
-        // first byte: version number
-        data.push(self.version);
+    // unwrap safety - the version is checked when the response is built or read
+    fn codec(&self) -> Codec {
+        Codec::for_version(self.version).unwrap()
+    }
+
+    // item hashes only need to go on the wire if some item is missing its data
+    fn has_elided_items(&self) -> bool {
+        self.items.iter().any(|item| item.elided)
+    }
+
+    // and media types only if some item has one
+    fn has_media_types(&self) -> bool {
+        self.items.iter().any(Item::has_media_type)
+    }
+
+    /// Put the data of elided items back from a local store.
+    ///
+    /// Fails with a `MissingItemError` if the store doesn't have one of them.
+    pub fn fill_items(&mut self, store: &impl ItemStore) -> Result<(), FroggiError> {
+        for item in self.items.iter_mut().filter(|item| item.elided) {
+            match store.get(&item.hash) {
+                Some(data) => {
+                    item.data = data.to_vec();
+                    item.elided = false;
+                    item.size = None;
+                }
+
+                None => {
+                    return Err(FroggiError::new(ErrorKind::MissingItemError {
+                        name: item.name.clone(),
+                        hash: item.hash,
+                    }))
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Keep the data of every item that wasn't elided in a local store, along with which items
+    /// the page referenced. `page` identifies the server and page the response came from.
+    pub fn remember_items(&self, page: &str, store: &mut impl ItemStore) {
+        for item in self.items.iter().filter(|item| !item.elided) {
+            store.insert(item.hash, &item.data);
+        }
+        store.insert_page(page, self.items.iter().map(|item| item.hash).collect());
+    }
+
+    /// Write the response to a sink of bytes.
+    ///
+    /// The page and items are written directly, without being copied into an intermediate
+    /// buffer first. Consider wrapping the sink in a `BufWriter`, since the headers are written
+    /// in small pieces.
+    pub fn write_to(&self, writer: &mut impl Write) -> Result<(), FroggiError> {
+        self.write_to_with_id(self.id, writer)
+    }
+
+    /// Write the response to a sink of bytes with a different client ID.
+    ///
+    /// Useful for servers that cache responses and send them to many clients.
+    pub fn write_to_with_id(&self, id: Uuid, writer: &mut impl Write) -> Result<(), FroggiError> {
+        let parts = Parts::plain(self, &HashSet::new());
+        self.write(self.codec(), id, self.has_elided_items(), &parts, writer)
+    }
+
+    /// Write the response with item hashes, leaving out the data of items the client already
+    /// has.
+    ///
+    /// `known` is the list of item hashes from the client's request.
+    pub fn write_to_eliding(
+        &self,
+        id: Uuid,
+        known: &[ContentHash],
+        writer: &mut impl Write,
+    ) -> Result<(), FroggiError> {
+        let parts = Parts::plain(self, &known.iter().copied().collect());
+        self.write(self.codec(), id, true, &parts, writer)
+    }
+
+    /// Write the response as an answer to a request.
+    ///
+    /// The response is written in the request's version of froggi, with a client ID, and with
+    /// the data of items the request says the client already has left out. The page and items
+    /// are compressed with the codec the client likes best out of those we support, if it
+    /// accepts any. Fails if the page and items don't fit in the request's version.
+    ///
+    /// Metadata requests only get the lengths and hashes of the page and items, unless this is a
+    /// redirect or an error, which are written whole.
+    pub fn write_for(
+        &self,
+        request: &Request,
+        id: Uuid,
+        writer: &mut impl Write,
+    ) -> Result<(), FroggiError> {
+        self.write_for_with(request, id, &[], writer)
+    }
+
+    // the same, using data compressed ahead of time if there's any with the right codec
+    fn write_for_with(
+        &self,
+        request: &Request,
+        id: Uuid,
+        compressed: &[Compressed],
+        writer: &mut impl Write,
+    ) -> Result<(), FroggiError> {
+        let codec = Codec::for_version(request.version())?;
+        let known = request.known_items();
+        let hashes = !known.is_empty() || self.has_elided_items();
+        let compression = Compression::negotiate(&request.accepted_compression());
+        let metadata = request.kind() == RequestKind::Metadata
+            && !matches!(self.kind, ResponseKind::Redirect | ResponseKind::Error);
+
+        let cached = compressed
+            .iter()
+            .find(|compressed| compressed.compression == compression);
+        let parts = Parts::new(
+            self,
+            &known.iter().copied().collect(),
+            compression,
+            metadata,
+            cached,
+        )?;
+        self.write(codec, id, hashes, &parts, writer)
+    }
 
-        // next byte: response kind
-        data.push(self.kind.into());
+    fn write(
+        &self,
+        codec: Codec,
+        id: Uuid,
+        hashes: bool,
+        parts: &Parts,
+        writer: &mut impl Write,
+    ) -> Result<(), FroggiError> {
+        check_page_and_items(codec, self)?;
+
+        let mut header = Vec::with_capacity(PAGE_OFFSET);
+        header.extend_from_slice(&FROGGI_MAGIC);
+
+        // first byte: version number
+        header.push(codec.version());
+
+        // next byte: response kind, with flags for item hashes, compression, metadata and media
+        // types.
+        // metadata items always have hashes, since there's nothing else to tell them apart by
+        let hashes = hashes || parts.metadata;
+        let mut kind: u8 = self.kind.into();
+        if hashes {
+            kind |= RESPONSE_FLAG_ITEM_HASHES;
+        }
+        if parts.compressed {
+            kind |= RESPONSE_FLAG_COMPRESSED;
+        }
+        if parts.metadata {
+            kind |= RESPONSE_FLAG_METADATA;
+        }
+        if parts.media_types {
+            kind |= RESPONSE_FLAG_MEDIA_TYPES;
+        }
+        header.push(kind);
 
         // next 16 bytes: client ID
-        data.extend_from_slice(self.id.as_bytes());
+        header.extend_from_slice(id.as_bytes());
+
+        // next four bytes, eight in version 1: total response length
+        let total = parts.total_len(codec, self.kind, hashes);
+        codec.write(Field::Total, total, &mut header)?;
 
-        // next four bytes: total response length
-        data.push(0);
-        data.push(0);
-        data.push(0);
-        data.push(0);
+        // next byte, only in error responses: error code
+        if let Some(code) = self.code {
+            header.push(code.into());
+        }
 
-        // next four bytes: page length
-        // unwrap safety - we checked that the page fits in a u32
-        let page_len = crate::serialize_to_four_bytes(self.page.len()).unwrap();
-        data.push(page_len[0]);
-        data.push(page_len[1]);
-        data.push(page_len[2]);
-        data.push(page_len[3]);
+        // next, only in item range responses: the offset of the range, and the item's length
+        if let Some(range) = self.range {
+            codec.write(Field::Offset, range.offset, &mut header)?;
+            codec.write(Field::Item, range.size, &mut header)?;
+        }
 
-        // next string: page
-        data.extend_from_slice(self.page.as_bytes());
+        // next byte, if flagged: page compression
+        if parts.compressed {
+            header.push(parts.page.compression.into());
+        }
 
-        // next byte: number of items
-        // overflow safety - we checked the number of items fits in a u8
-        data.push(self.items.len() as u8);
+        // next: page length
+        codec.write(Field::Page, parts.page.len, &mut header)?;
+        writer.write_all(&header)?;
+
+        // next string: page, unless this is a metadata response
+        writer.write_all(&parts.page.data)?;
+
+        // next: number of items
+        let mut num_items = Vec::with_capacity(VARINT_MAX_LEN);
+        codec.write(Field::Items, self.items.len(), &mut num_items)?;
+        writer.write_all(&num_items)?;
+
+        for (item, part) in parts.items.iter() {
+            let mut item_header =
+                Vec::with_capacity(parts.item_header_len(codec, item, part, hashes));
 
-        for item in self.items.iter() {
             // next byte: item kind
-            data.push(item.kind.into());
+            item_header.push(item.kind.into());
 
-            // next byte: item name length
-            // overflow safety - we checked the item name length fits in a u8
-            data.push(item.name.len() as u8);
+            // next: item name length
+            codec.write(Field::ItemName, item.name.len(), &mut item_header)?;
 
             // next string: item name
-            data.extend_from_slice(item.name.as_bytes());
+            item_header.extend_from_slice(item.name.as_bytes());
+
+            // next, if flagged: media type length and media type, empty if the item has none
+            if parts.media_types {
+                let media_type = item.media_type.as_deref().unwrap_or_default();
+                codec.write(Field::MediaType, media_type.len(), &mut item_header)?;
+                item_header.extend_from_slice(media_type.as_bytes());
+            }
+
+            // next 32 bytes, if flagged: item hash
+            if hashes {
+                item_header.extend_from_slice(item.hash.as_bytes());
+            }
+
+            // next byte, if flagged: item compression
+            if parts.compressed {
+                item_header.push(part.compression.into());
+            }
+
+            // next: item length, zero if the item was elided
+            codec.write(Field::Item, part.len, &mut item_header)?;
+            writer.write_all(&item_header)?;
+
+            // next string: item data, unless this is a metadata response
+            writer.write_all(&part.data)?;
+        }
 
-            // next four bytes: item length
-            // unwrap safety - we checked the item size fits in a u32
-            let item_len = crate::serialize_to_four_bytes(item.data.len()).unwrap();
-            data.push(item_len[0]);
-            data.push(item_len[1]);
-            data.push(item_len[2]);
-            data.push(item_len[3]);
+        Ok(())
+    }
 
-            // next string: item data
-            data.extend_from_slice(&item.data);
-        }
+    /// Convert the page into bytes
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.encoded_len());
 
-        // unwrap safety - we checked that the size of everything can fit in a u32
-        let total_len = crate::serialize_to_four_bytes(data.len()).unwrap();
-        data[TOTAL_RESPONSE_LENGTH_OFFSET] = total_len[0];
-        data[TOTAL_RESPONSE_LENGTH_OFFSET + 1] = total_len[1];
-        data[TOTAL_RESPONSE_LENGTH_OFFSET + 2] = total_len[2];
-        data[TOTAL_RESPONSE_LENGTH_OFFSET + 3] = total_len[3];
+        // unwrap safety - writing to a Vec never fails
+        self.write_to(&mut data).unwrap();
 
         data
     }
@@ -293,48 +1083,624 @@ impl Into<Vec<u8>> for Response {
     }
 }
 
-pub struct ResponseBuilder {
-    version: u8,
-    kind: Option<ResponseKind>,
-    id: Option<Uuid>,
-    page: Option<String>,
-    items: Vec<Item>,
+/// A response that is sent over and over, like a page a server keeps in memory.
+///
+/// The page and items are compressed with every codec we support when it's created, so they
+/// don't have to be compressed again for every client.
+#[derive(Debug)]
+pub struct CachedResponse {
+    response: Response,
+    compressed: Vec<Compressed>,
 }
 
-impl Default for ResponseBuilder {
-    fn default() -> Self {
-        Self {
-            version: crate::FROGGI_VERSION,
-            kind: None,
-            id: None,
-            page: None,
-            items: Vec::new(),
-        }
+impl CachedResponse {
+    pub fn new(response: Response) -> Result<CachedResponse, FroggiError> {
+        let compressed = Compression::supported()
+            .iter()
+            .map(|&compression| Compressed::new(&response, compression))
+            .collect::<Result<_, FroggiError>>()?;
+
+        Ok(CachedResponse {
+            response,
+            compressed,
+        })
     }
-}
 
-impl ResponseBuilder {
-    pub fn new() -> Self {
-        Self::default()
+    /// Get the response
+    pub fn response(&self) -> &Response {
+        &self.response
     }
 
-    pub fn build(self) -> Result<Response, FroggiError> {
-        let version = self.version;
-        let kind = self.kind.unwrap_or(ResponseKind::PageNoItems);
-        let id = self.id.unwrap_or(Uuid::new_v4());
-        let page = self.page.unwrap_or(String::new());
-        let items = self.items;
-        check_page_and_items(&page, &items)?;
+    /// Write the response as an answer to a request, like `Response::write_for`.
+    pub fn write_for(
+        &self,
+        request: &Request,
+        id: Uuid,
+        writer: &mut impl Write,
+    ) -> Result<(), FroggiError> {
+        self.response
+            .write_for_with(request, id, &self.compressed, writer)
+    }
+}
 
-        Ok(Response {
-            version,
-            kind,
-            id,
+/// Reads a response incrementally.
+///
+/// The page is read up front, and items are handed back one at a time as they arrive. Items that
+/// are not read to the end are skipped when the next item is requested. Metadata responses have
+/// no page or item data to read, only their lengths.
+#[derive(Debug)]
+pub struct ResponseReader<R> {
+    reader: R,
+    codec: Codec,
+    version: u8,
+    kind: ResponseKind,
+    id: Uuid,
+    code: Option<ErrorCode>,
+    range: Option<ItemRange>,
+    page: String,
+    page_len: usize,
+    hashes: bool,
+    compressed: bool,
+    metadata: bool,
+    media_types: bool,
+    items_left: usize,
+    unread: u64,
+    limits: DecodeLimits,
+    total: usize,
+    remaining: usize,
+    // how long the response is with everything that's been decompressed so far decompressed,
+    // which is held to the total length limit too
+    decompressed_total: usize,
+}
+
+impl<R: Read> ResponseReader<R> {
+    /// Read the response header and page from a source of bytes, with the default limits.
+    pub fn new(reader: R) -> Result<Self, FroggiError> {
+        Self::with_limits(reader, DecodeLimits::default())
+    }
+
+    /// Read the response header and page from a source of bytes, with limits.
+    ///
+    /// Item lengths are checked against the limits as each item is reached.
+    pub fn with_limits(mut reader: R, limits: DecodeLimits) -> Result<Self, FroggiError> {
+        // response header, 22 bytes long
+        let mut header = [0u8; FROGGI_HEADER_LEN];
+        reader.read_exact(&mut header)?;
+        let codec = crate::check_header(&header)?;
+
+        // version and kind are first two bytes
+        let version = header[FROGGI_VERSION_OFFSET];
+        let kind_byte = header[REQUEST_RESPONSE_KIND_OFFSET];
+        let kind = (kind_byte & RESPONSE_KIND_MASK).into();
+
+        let flags = kind_byte & !RESPONSE_KIND_MASK;
+        if flags & !RESPONSE_FLAGS != 0 {
+            return Err(FroggiError::new(ResponseFormatError)
+                .msg(format!("Unknown response flags {:#04x}.", flags)));
+        }
+        let hashes = flags & RESPONSE_FLAG_ITEM_HASHES != 0;
+        let compressed = flags & RESPONSE_FLAG_COMPRESSED != 0;
+        let metadata = flags & RESPONSE_FLAG_METADATA != 0;
+        let media_types = flags & RESPONSE_FLAG_MEDIA_TYPES != 0;
+
+        // next 16 is client ID
+        let id = Uuid::from_bytes(
+            header[REQUEST_RESPONSE_UUID_OFFSET..TOTAL_RESPONSE_LENGTH_OFFSET]
+                .try_into()
+                .unwrap(),
+        );
+
+        // next four bytes, eight in version 1, is response length
+        let total = codec.read(Field::Total, &mut reader)?;
+        limits.check(Limit::Total, total)?;
+
+        // error responses have a one byte error code next
+        let code = if let ResponseKind::Error = kind {
+            let mut code = [0u8; ERROR_CODE_LEN];
+            reader.read_exact(&mut code)?;
+            Some(code[0].into())
+        } else {
+            None
+        };
+
+        // item range responses say where the item they have goes next
+        let range = if let ResponseKind::ItemRange = kind {
+            Some(ItemRange {
+                offset: codec.read(Field::Offset, &mut reader)?,
+                size: codec.read(Field::Item, &mut reader)?,
+            })
+        } else {
+            None
+        };
+
+        // compressed responses say how the page was compressed next
+        let page_compression = read_compression(compressed, &mut reader)?;
+
+        // next is page length. metadata responses only say how long the page is, so it isn't
+        // limited
+        let page_len = codec.read(Field::Page, &mut reader)?;
+        let sent_len = if metadata { 0 } else { page_len };
+        limits.check(Limit::Page, sent_len)?;
+
+        // the page has to fit in the total length
+        let header_len = sum_lens(&[
+            FROGGI_HEADER_LEN,
+            codec.len_of(Field::Total, total),
+            kind_header_len(codec, kind, range),
+            compression_len(compressed),
+            codec.len_of(Field::Page, page_len),
+            sent_len,
+        ]);
+        let remaining = total.checked_sub(header_len).ok_or_else(|| {
+            FroggiError::new(ErrorKind::LengthMismatchError {
+                expected: total,
+                got: header_len,
+            })
+        })?;
+
+        // read page
+        let mut page_buf = vec![0; sent_len];
+        reader.read_exact(&mut page_buf)?;
+        let mut decompressed_total = total;
+        let page_buf = compression::decompress(
+            page_compression,
+            page_buf,
+            Limit::Page,
+            &limits,
+            &mut decompressed_total,
+        )?;
+        let page = String::from_utf8(page_buf)?;
+
+        // number of items, one byte in version 0
+        let items_left = codec.read(Field::Items, &mut reader)?;
+        limits.check(Limit::Items, items_left)?;
+
+        let mut response = ResponseReader {
+            reader,
+            codec,
+            version,
+            kind,
+            id,
+            code,
+            range,
             page,
-            items,
+            page_len,
+            hashes,
+            compressed,
+            metadata,
+            media_types,
+            items_left,
+            unread: 0,
+            limits,
+            total,
+            remaining,
+            decompressed_total,
+        };
+        response.consume(&[codec.len_of(Field::Items, items_left)])?;
+
+        Ok(response)
+    }
+
+    /// Get the version of the response
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// Get the kind of the response
+    pub fn kind(&self) -> ResponseKind {
+        self.kind
+    }
+
+    /// Get the client ID provided by the server
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    /// Get the error code, if this is an error response
+    pub fn error_code(&self) -> Option<ErrorCode> {
+        self.code
+    }
+
+    /// Get where the item goes in the whole item, if this is an item range response
+    pub fn item_range(&self) -> Option<ItemRange> {
+        self.range
+    }
+
+    /// Get the page of the response, un-parsed
+    pub fn page(&self) -> &str {
+        &self.page
+    }
+
+    /// Get the length of the page, even if it was left out of a metadata response
+    pub fn page_len(&self) -> usize {
+        self.page_len
+    }
+
+    /// True if this is a metadata response, which only describes the page and items
+    pub fn is_metadata(&self) -> bool {
+        self.metadata
+    }
+
+    /// Parse the page of the response. Zero-copy.
+    #[cfg(feature = "markup")]
+    pub fn parse(&self) -> Result<crate::markup::Page<'_>, Vec<FroggiError>> {
+        crate::markup::parse::parse(&self.page)
+    }
+
+    /// Get the number of items that have not been handed back yet
+    pub fn items_left(&self) -> usize {
+        self.items_left
+    }
+
+    /// Read the header of the next item, skipping whatever is left of the previous one.
+    ///
+    /// Returns `None` once every item has been handed back.
+    pub fn next_item(&mut self) -> Result<Option<ItemReader<'_, R>>, FroggiError> {
+        self.skip_unread()?;
+
+        if self.items_left == 0 {
+            // everything the header promised should have been read by now
+            if self.remaining != 0 {
+                return Err(FroggiError::new(ErrorKind::LengthMismatchError {
+                    expected: self.total,
+                    got: self.total - self.remaining,
+                }));
+            }
+
+            return Ok(None);
+        }
+        self.items_left -= 1;
+
+        if self.metadata && !self.hashes {
+            return Err(FroggiError::new(ResponseFormatError)
+                .msg_str("The items of a metadata response have no hashes."));
+        }
+
+        // item kind, 1 byte
+        let mut item_kind = [0u8; ITEM_KIND_LEN];
+        self.reader.read_exact(&mut item_kind)?;
+        let kind = item_kind[0].into();
+
+        // length of the item's name, 1 byte in version 0
+        let item_name_len = self.codec.read(Field::ItemName, &mut self.reader)?;
+        self.limits.check(Limit::ItemName, item_name_len)?;
+        self.consume(&[
+            ITEM_KIND_LEN,
+            self.codec.len_of(Field::ItemName, item_name_len),
+            item_name_len,
+        ])?;
+
+        // item name
+        let mut name_buf = vec![0; item_name_len];
+        self.reader.read_exact(&mut name_buf)?;
+        let name = String::from_utf8(name_buf)?;
+
+        // item media type and its length, 1 byte in version 0, if flagged. empty if the item
+        // doesn't have one
+        let media_type = if self.media_types {
+            let media_type_len = self.codec.read(Field::MediaType, &mut self.reader)?;
+            self.limits.check(Limit::MediaType, media_type_len)?;
+            self.consume(&[
+                self.codec.len_of(Field::MediaType, media_type_len),
+                media_type_len,
+            ])?;
+
+            let mut media_type = vec![0; media_type_len];
+            self.reader.read_exact(&mut media_type)?;
+            Some(String::from_utf8(media_type)?).filter(|media_type| !media_type.is_empty())
+        } else {
+            None
+        };
+
+        // item hash, 32 bytes, if flagged
+        let hash = if self.hashes {
+            let mut hash = [0u8; CONTENT_HASH_LEN];
+            self.reader.read_exact(&mut hash)?;
+            Some(ContentHash::from_bytes(hash))
+        } else {
+            None
+        };
+
+        // item compression, 1 byte, if flagged
+        let compression = read_compression(self.compressed, &mut self.reader)?;
+
+        // item length, 4 bytes in version 0. metadata responses don't send the item
+        let len = self.codec.read(Field::Item, &mut self.reader)?;
+        let sent_len = if self.metadata { 0 } else { len };
+        self.limits.check(Limit::ItemSize, sent_len)?;
+
+        let hash_len = if self.hashes { CONTENT_HASH_LEN } else { 0 };
+        self.consume(&[
+            hash_len,
+            compression_len(self.compressed),
+            self.codec.len_of(Field::Item, len),
+            sent_len,
+        ])?;
+
+        // the item itself is read through the ItemReader
+        self.unread = sent_len as u64;
+
+        Ok(Some(ItemReader {
+            name,
+            kind,
+            media_type,
+            hash,
+            compression,
+            len,
+            response: self,
+        }))
+    }
+
+    // account for bytes in the total response length
+    fn consume(&mut self, lens: &[usize]) -> Result<(), FroggiError> {
+        let len = sum_lens(lens);
+        self.remaining = self.remaining.checked_sub(len).ok_or_else(|| {
+            FroggiError::new(ErrorKind::LengthMismatchError {
+                expected: self.total,
+                got: (self.total - self.remaining).saturating_add(len),
+            })
+        })?;
+
+        Ok(())
+    }
+
+    fn skip_unread(&mut self) -> Result<(), FroggiError> {
+        let skipped = io::copy(&mut (&mut self.reader).take(self.unread), &mut io::sink())?;
+
+        if skipped != self.unread {
+            return Err(FroggiError::io(io::ErrorKind::UnexpectedEof.into())
+                .msg_str("The response ended in the middle of an item."));
+        }
+
+        self.unread = 0;
+        Ok(())
+    }
+}
+
+/// An item that is being read from a response.
+///
+/// Reading from it yields the item's data as it was sent, and nothing past its end. If the item
+/// was compressed, that's the compressed data. `into_item` decompresses it.
+#[derive(Debug)]
+pub struct ItemReader<'a, R> {
+    name: String,
+    kind: ItemKind,
+    media_type: Option<String>,
+    hash: Option<ContentHash>,
+    compression: Compression,
+    len: usize,
+    response: &'a mut ResponseReader<R>,
+}
+
+impl<R: Read> ItemReader<'_, R> {
+    /// Get the name of the item
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the kind of the item
+    pub fn kind(&self) -> ItemKind {
+        self.kind
+    }
+
+    /// Get the media type the server gave the item, if it gave it one
+    pub fn media_type(&self) -> Option<&str> {
+        self.media_type.as_deref()
+    }
+
+    /// Get the hash of the item's data, if the response has item hashes
+    pub fn hash(&self) -> Option<ContentHash> {
+        self.hash
+    }
+
+    /// True if the server left out the item's data because the client already has it, or because
+    /// this is a metadata response.
+    ///
+    /// An elided item has no data but a hash that isn't the hash of nothing.
+    pub fn is_elided(&self) -> bool {
+        if self.response.metadata {
+            return true;
+        }
+
+        match self.hash {
+            Some(hash) => self.len == 0 && hash != ContentHash::of(&[]),
+            None => false,
+        }
+    }
+
+    /// Get how the item's data was compressed
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// Get the total length of the item's data, as it was sent. In metadata responses, this is
+    /// the length of the data that was left out.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// True if the item has no data
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Read the rest of the item into memory, decompressing it if it was compressed.
+    ///
+    /// If the response has item hashes, the data is checked against the item's hash.
+    pub fn into_item(mut self) -> Result<Item, FroggiError> {
+        let media_type = self.media_type.take();
+        Ok(Item {
+            media_type,
+            ..self.read_item()?
         })
     }
 
+    fn read_item(mut self) -> Result<Item, FroggiError> {
+        // unwrap safety - metadata items are checked for hashes when they're reached
+        if self.response.metadata {
+            return Ok(Item::described(
+                self.name,
+                self.kind,
+                self.hash.unwrap(),
+                self.len,
+            ));
+        }
+
+        if self.is_elided() {
+            // unwrap safety - only items with hashes are elided
+            return Ok(Item::elided(self.name, self.kind, self.hash.unwrap()));
+        }
+
+        let mut data = Vec::with_capacity(self.response.unread as usize);
+        self.read_to_end(&mut data)?;
+
+        if self.response.unread != 0 {
+            return Err(FroggiError::io(io::ErrorKind::UnexpectedEof.into())
+                .msg(format!("The item {} was cut short.", self.name)));
+        }
+
+        let limits = self.response.limits;
+        let data = compression::decompress(
+            self.compression,
+            data,
+            Limit::ItemSize,
+            &limits,
+            &mut self.response.decompressed_total,
+        )?;
+        let item = Item::new(self.name, self.kind, data);
+        match self.hash {
+            Some(hash) if hash != item.hash => Err(FroggiError::new(ResponseFormatError)
+                .msg(format!("The item {} doesn't match its hash.", item.name))),
+            _ => Ok(item),
+        }
+    }
+}
+
+impl<R: Read> Read for ItemReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let max = std::cmp::min(buf.len() as u64, self.response.unread) as usize;
+
+        // don't touch the underlying reader past the end of the item, it may be a connection
+        // that's waiting for the next response
+        if max == 0 {
+            return Ok(0);
+        }
+
+        let read = self.response.reader.read(&mut buf[..max])?;
+        self.response.unread -= read as u64;
+        Ok(read)
+    }
+}
+
+pub struct ResponseBuilder {
+    version: u8,
+    kind: Option<ResponseKind>,
+    id: Option<Uuid>,
+    code: Option<ErrorCode>,
+    page: Option<String>,
+    metadata: bool,
+    page_len: Option<usize>,
+    range: Option<ItemRange>,
+    items: Vec<Item>,
+    bad_anchor: Option<String>,
+}
+
+impl Default for ResponseBuilder {
+    fn default() -> Self {
+        Self {
+            version: crate::FROGGI_VERSION,
+            kind: None,
+            id: None,
+            code: None,
+            page: None,
+            metadata: false,
+            page_len: None,
+            range: None,
+            items: Vec::new(),
+            bad_anchor: None,
+        }
+    }
+}
+
+impl ResponseBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn build(self) -> Result<Response, FroggiError> {
+        let version = self.version;
+        let kind = self.kind.unwrap_or(ResponseKind::PageNoItems);
+        let id = self.id.unwrap_or(Uuid::nil());
+
+        // the high bits of the kind byte are flags
+        let kind_byte: u8 = kind.into();
+        if kind_byte & !RESPONSE_KIND_MASK != 0 {
+            return Err(
+                FroggiError::new(ErrorKind::ResponseFormatError).msg(format!(
+                    "The response kind {:#04x} is too large.",
+                    kind_byte
+                )),
+            );
+        }
+
+        if let Some(anchor) = self.bad_anchor {
+            return Err(
+                FroggiError::new(ErrorKind::ResponseFormatError).msg(format!(
+                    "The anchor {} can't be written in markup because it has a quote in it.",
+                    anchor
+                )),
+            );
+        }
+
+        let page = self.page.unwrap_or(String::new());
+        let codec = Codec::for_version(version)?;
+
+        // metadata responses keep the lengths of the page and items, but not their data
+        let (page, page_len, items) = if self.metadata {
+            let page_len = self.page_len.unwrap_or(page.len());
+            let items = self.items.into_iter().map(Item::into_described).collect();
+            (String::new(), Some(page_len), items)
+        } else {
+            (page, None, self.items)
+        };
+
+        // error responses always have a code, and nothing else does
+        let code = match kind {
+            ResponseKind::Error => Some(self.code.unwrap_or(ErrorCode::Unknown(15))),
+            _ => None,
+        };
+
+        // and so do item ranges, which are the whole item unless we're told otherwise
+        let range = match kind {
+            ResponseKind::ItemRange => Some(self.range.unwrap_or(ItemRange {
+                offset: 0,
+                size: items.first().and_then(Item::size).unwrap_or_default(),
+            })),
+            _ => None,
+        };
+
+        if let Some(range) = range {
+            if range.offset > range.size {
+                return Err(FroggiError::new(ErrorKind::ResponseFormatError)
+                    .msg_str("The item range starts past the end of the item."));
+            }
+        }
+
+        let response = Response {
+            version,
+            kind,
+            id,
+            code,
+            range,
+            page,
+            page_len,
+            items,
+        };
+        check_page_and_items(codec, &response)?;
+
+        Ok(response)
+    }
+
     pub fn version(self, version: u8) -> Self {
         Self { version, ..self }
     }
@@ -353,6 +1719,35 @@ impl ResponseBuilder {
         }
     }
 
+    /// Make this a redirect to another URL, which may be relative to the requested URL.
+    pub fn redirect(self, target: &str) -> Self {
+        Self {
+            kind: Some(ResponseKind::Redirect),
+            page: Some(target.into()),
+            items: Vec::new(),
+            ..self
+        }
+    }
+
+    /// Tell the client its cached copy is still good.
+    pub fn not_modified(self) -> Self {
+        Self {
+            kind: Some(ResponseKind::NotModified),
+            page: None,
+            items: Vec::new(),
+            ..self
+        }
+    }
+
+    /// Make this an error response with a code.
+    pub fn error(self, code: ErrorCode) -> Self {
+        Self {
+            kind: Some(ResponseKind::Error),
+            code: Some(code),
+            ..self
+        }
+    }
+
     pub fn page(self, page: String) -> Self {
         Self {
             page: Some(page),
@@ -373,6 +1768,64 @@ impl ResponseBuilder {
             ..self
         }
     }
+
+    /// Send part of an item, starting at `offset` and going for `len` bytes or to the end of the
+    /// item, whichever comes first.
+    ///
+    /// Building the response fails if the offset is past the end of the item.
+    pub fn item_range(self, item: &Item, offset: usize, len: Option<usize>) -> Self {
+        let data = item.data();
+        let start = offset.min(data.len());
+        let end = match len {
+            Some(len) => start.saturating_add(len).min(data.len()),
+            None => data.len(),
+        };
+
+        Self {
+            kind: Some(ResponseKind::ItemRange),
+            page: None,
+            range: Some(ItemRange {
+                offset,
+                size: data.len(),
+            }),
+            items: vec![Item {
+                media_type: item.media_type.clone(),
+                ..Item::new(item.name.clone(), item.kind, data[start..end].to_vec())
+            }],
+            ..self
+        }
+    }
+
+    /// Only describe the page and items, leaving out their data but keeping their lengths and
+    /// hashes. This is how a metadata request is answered.
+    pub fn metadata(self) -> Self {
+        Self {
+            metadata: true,
+            ..self
+        }
+    }
+
+    /// Add page expressions to be inserted at an anchor in a page the client already has.
+    ///
+    /// The expressions are markup, and may use styles from a page style expression set with
+    /// `page` before any anchored expressions are added. Anchors can't have quotes in them, since
+    /// markup strings end at either kind of quote, and building the response fails if one does.
+    pub fn anchored(mut self, anchor: &str, expressions: &str) -> Self {
+        if anchor.contains(['"', '\'']) {
+            self.bad_anchor.get_or_insert_with(|| anchor.into());
+            return self;
+        }
+
+        let page = self.page.get_or_insert_with(String::new);
+        if !page.is_empty() && !page.ends_with('\n') {
+            page.push('\n');
+        }
+        page.push_str(&format!("(# \"{}\")\n", anchor));
+        page.push_str(expressions);
+
+        self.kind = Some(ResponseKind::AdditionalExpressions);
+        self
+    }
 }
 
 #[rustfmt::skip]
@@ -380,7 +1833,7 @@ impl ResponseBuilder {
 const DATA_REAL: &[u8] = &[
     0xf0, 0x9f, 0x90, 0xb8,                                     // froggi magic 🐸
     0,                                                                                      // version
-    0,                                                                                      // response kind
+    1,                                                                                      // response kind
     0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,                                         // client ID
     105, 1, 0, 0,                                                                           // total length
     60, 0, 0, 0,                                                                            // page length
@@ -414,12 +1867,13 @@ const DATA_REAL: &[u8] = &[
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::request::RequestKind;
 
     #[test]
     fn from_bytes() {
         let mut bytes = DATA_REAL.clone();
         let response = Response::from_bytes(&mut bytes).unwrap();
-        assert_eq!(response.version, crate::FROGGI_VERSION);
+        assert_eq!(response.version, 0);
         assert_eq!(
             &response.page,
             r#"(img "white.png")
@@ -451,6 +1905,7 @@ mod test {
         );
 
         let response = ResponseBuilder::default()
+            .version(0)
             .id(Uuid::nil())
             .page(page)
             .items(vec![white, magenta])
@@ -464,4 +1919,914 @@ mod test {
 
         crate::test::test_bytes(DATA_REAL, &data_test).unwrap();
     }
+
+    #[test]
+    fn anchored() {
+        let response = ResponseBuilder::default()
+            .page(String::from("{(quiet (fg \"777777\"))}"))
+            .anchored("comments", "({quiet} \"first!\")")
+            .anchored("footer", "(\"updated just now\")")
+            .build()
+            .unwrap();
+
+        assert!(matches!(
+            response.kind(),
+            ResponseKind::AdditionalExpressions
+        ));
+        assert_eq!(
+            response.page(),
+            r#"{(quiet (fg "777777"))}
+(# "comments")
+({quiet} "first!")
+(# "footer")
+("updated just now")"#
+        );
+    }
+
+    #[test]
+    fn anchor_quotes() {
+        for anchor in ["say \"hi\"", "frog's"].iter() {
+            assert!(matches!(
+                ResponseBuilder::default()
+                    .anchored(anchor, "(\"hello\")")
+                    .anchored("footer", "(\"updated just now\")")
+                    .build()
+                    .unwrap_err()
+                    .kind(),
+                ErrorKind::ResponseFormatError
+            ));
+        }
+    }
+
+    #[test]
+    fn bad_header() {
+        let mut bytes = DATA_REAL.to_vec();
+        bytes[..4].copy_from_slice(b"frgi");
+        assert!(matches!(
+            Response::from_bytes(&mut bytes.as_slice()).unwrap_err().kind(),
+            ErrorKind::MagicError { got } if got == b"frgi"
+        ));
+
+        let mut bytes = DATA_REAL.to_vec();
+        bytes[FROGGI_VERSION_OFFSET] = 0xfe;
+        assert!(matches!(
+            Response::from_bytes(&mut bytes.as_slice())
+                .unwrap_err()
+                .kind(),
+            ErrorKind::VersionError { got: 0xfe }
+        ));
+    }
+
+    #[test]
+    fn error_code() {
+        let response = ResponseBuilder::default()
+            .version(0)
+            .page(String::from("('not found')"))
+            .error(ErrorCode::NotFound)
+            .build()
+            .unwrap();
+
+        let bytes = response.bytes();
+        assert_eq!(bytes.len(), response.encoded_len());
+        assert_eq!(bytes[PAGE_LENGTH_OFFSET], u8::from(ErrorCode::NotFound));
+
+        let response = Response::from_bytes(&mut bytes.as_slice()).unwrap();
+        assert!(matches!(response.kind(), ResponseKind::Error));
+        assert!(matches!(response.error_code(), Some(ErrorCode::NotFound)));
+        assert_eq!(response.page(), "('not found')");
+
+        let error = response.error_for_status().unwrap_err();
+        assert!(matches!(
+            error.kind(),
+            ErrorKind::StatusError {
+                code: ErrorCode::NotFound
+            }
+        ));
+
+        // other responses don't have a code
+        let mut bytes = DATA_REAL;
+        let response = Response::from_bytes(&mut bytes).unwrap();
+        assert!(response.error_code().is_none());
+        assert!(response.error_for_status().is_ok());
+
+        // errors without a code are unknown errors
+        let response = ResponseBuilder::default()
+            .kind(ResponseKind::Error)
+            .build()
+            .unwrap();
+        assert!(matches!(
+            response.error_code(),
+            Some(ErrorCode::Unknown(15))
+        ));
+    }
+
+    #[test]
+    fn unknown_kinds() {
+        // kinds and codes we don't know about are kept, so they're written back the same
+        let mut bytes = DATA_REAL.to_vec();
+        bytes[REQUEST_RESPONSE_KIND_OFFSET] = 0x09;
+        bytes[PAGE_OFFSET + 60 + NUM_ITEMS_LEN] = 0x42;
+
+        let response = Response::from_bytes(&mut bytes.as_slice()).unwrap();
+        assert_eq!(response.kind(), ResponseKind::Unknown(9));
+        assert_eq!(response.items()[0].kind(), ItemKind::Unknown(0x42));
+        assert_eq!(response.bytes(), bytes);
+
+        let mut bytes = ResponseBuilder::default()
+            .version(0)
+            .error(ErrorCode::NotFound)
+            .build()
+            .unwrap()
+            .bytes();
+        bytes[PAGE_LENGTH_OFFSET] = 9;
+
+        let response = Response::from_bytes(&mut bytes.as_slice()).unwrap();
+        assert_eq!(response.error_code(), Some(ErrorCode::Unknown(9)));
+        assert_eq!(response.bytes(), bytes);
+
+        // kinds that run into the flags can't be written at all
+        for kind in &[0x15, 0x45] {
+            assert!(matches!(
+                ResponseBuilder::default()
+                    .kind(ResponseKind::Unknown(*kind))
+                    .build()
+                    .unwrap_err()
+                    .kind(),
+                ErrorKind::ResponseFormatError
+            ));
+        }
+    }
+
+    #[test]
+    fn content_hash() {
+        let mut bytes = DATA_REAL;
+        let response = Response::from_bytes(&mut bytes).unwrap();
+        let hash = response.content_hash();
+
+        // the client ID doesn't matter
+        let mut bytes = response.bytes();
+        bytes[REQUEST_RESPONSE_UUID_OFFSET] = 0xff;
+        let response = Response::from_bytes(&mut bytes.as_slice()).unwrap();
+        assert_eq!(response.content_hash(), hash);
+
+        // but the items do
+        let response = ResponseBuilder::default()
+            .page(response.page().into())
+            .item(Item::new("white.png".into(), ItemKind::Image, vec![]))
+            .build()
+            .unwrap();
+        assert_ne!(response.content_hash(), hash);
+
+        // and so does the page
+        let page = |page: &str| {
+            ResponseBuilder::default()
+                .page(page.into())
+                .build()
+                .unwrap()
+                .content_hash()
+        };
+        assert_eq!(page("('hi')"), page("('hi')"));
+        assert_ne!(page("('hi')"), page("('hello')"));
+    }
+
+    #[test]
+    fn length_mismatch() {
+        let with_total = |total: usize| {
+            let mut bytes = DATA_REAL.to_vec();
+            bytes[TOTAL_RESPONSE_LENGTH_OFFSET..PAGE_LENGTH_OFFSET]
+                .copy_from_slice(&crate::serialize_to_four_bytes(total).unwrap());
+            Response::from_bytes(&mut bytes.as_slice()).unwrap_err()
+        };
+
+        for total in &[DATA_REAL.len() - 1, DATA_REAL.len() + 1, PAGE_OFFSET] {
+            assert!(matches!(
+                with_total(*total).kind(),
+                ErrorKind::LengthMismatchError { expected, .. } if expected == total
+            ));
+        }
+    }
+
+    #[test]
+    fn limits() {
+        let limit = |limits: DecodeLimits| {
+            let mut bytes = DATA_REAL;
+            match Response::from_bytes_with_limits(&mut bytes, limits)
+                .unwrap_err()
+                .kind()
+            {
+                ErrorKind::LimitError { limit, .. } => *limit,
+                _ => panic!(),
+            }
+        };
+
+        let default = DecodeLimits::default();
+        assert_eq!(
+            limit(DecodeLimits {
+                max_page: 10,
+                ..default
+            }),
+            Limit::Page
+        );
+        assert_eq!(
+            limit(DecodeLimits {
+                max_items: 1,
+                ..default
+            }),
+            Limit::Items
+        );
+        assert_eq!(
+            limit(DecodeLimits {
+                max_item_size: 10,
+                ..default
+            }),
+            Limit::ItemSize
+        );
+        assert_eq!(
+            limit(DecodeLimits {
+                max_total: DATA_REAL.len() - 1,
+                ..default
+            }),
+            Limit::Total
+        );
+
+        let mut bytes = DATA_REAL;
+        assert!(Response::from_bytes_with_limits(&mut bytes, DecodeLimits::unlimited()).is_ok());
+    }
+
+    #[test]
+    fn item_name_limit() {
+        let response = ResponseBuilder::default()
+            .item(Item::new(
+                "frog.png".into(),
+                ItemKind::Image,
+                b"frog".to_vec(),
+            ))
+            .build()
+            .unwrap();
+        let mut bytes = response.bytes();
+        assert_eq!(bytes[FROGGI_VERSION_OFFSET], 1);
+
+        // claim the name is as long as a version 1 length can be
+        let name = bytes
+            .windows(8)
+            .position(|window| window == b"frog.png")
+            .unwrap();
+        assert_eq!(bytes[name - 1], 8);
+        bytes.splice(name - 1..name, [0xff; 9].iter().copied().chain(Some(0x01)));
+
+        assert!(matches!(
+            Response::from_bytes(&mut bytes.as_slice())
+                .unwrap_err()
+                .kind(),
+            ErrorKind::LimitError {
+                limit: Limit::ItemName,
+                got: usize::MAX,
+                ..
+            }
+        ));
+
+        // without limits, it doesn't fit in the total length
+        assert!(matches!(
+            Response::from_bytes_with_limits(&mut bytes.as_slice(), DecodeLimits::unlimited())
+                .unwrap_err()
+                .kind(),
+            ErrorKind::LengthMismatchError { .. }
+        ));
+    }
+
+    #[test]
+    fn media_type_limit() {
+        let response = ResponseBuilder::default()
+            .item(
+                Item::new("frog.rs".into(), ItemKind::Text, b"frog".to_vec())
+                    .with_media_type("text/x-rust"),
+            )
+            .build()
+            .unwrap();
+        let limits = DecodeLimits {
+            max_media_type: 10,
+            ..DecodeLimits::default()
+        };
+
+        assert!(matches!(
+            Response::from_bytes_with_limits(&mut response.bytes().as_slice(), limits)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::LimitError {
+                limit: Limit::MediaType,
+                got: 11,
+                max: 10
+            }
+        ));
+        assert_eq!(
+            Response::from_bytes(&mut response.bytes().as_slice())
+                .unwrap()
+                .items()[0]
+                .media_type(),
+            "text/x-rust"
+        );
+    }
+
+    #[test]
+    fn write_to() {
+        let mut bytes = DATA_REAL;
+        let response = Response::from_bytes(&mut bytes).unwrap();
+        assert_eq!(response.encoded_len(), DATA_REAL.len());
+
+        let mut data_test = Vec::new();
+        response.write_to(&mut data_test).unwrap();
+        crate::test::test_bytes(DATA_REAL, &data_test).unwrap();
+    }
+
+    #[test]
+    fn version_1() {
+        let mut bytes = DATA_REAL;
+        let response = Response::from_bytes(&mut bytes).unwrap();
+
+        // the total length is eight bytes, and every other length fits in one or two
+        let request = Request::new("index.fml", RequestKind::PageItems)
+            .unwrap()
+            .with_version(1)
+            .unwrap();
+        let mut bytes = Vec::new();
+        response
+            .write_for(&request, Uuid::nil(), &mut bytes)
+            .unwrap();
+        assert_eq!(bytes[FROGGI_VERSION_OFFSET], 1);
+        assert_eq!(bytes.len(), DATA_REAL.len() + 4 - 3 - 2 * 3);
+
+        let v1 = Response::from_bytes(&mut bytes.as_slice()).unwrap();
+        assert_eq!(v1.version(), 1);
+        assert_eq!(v1.encoded_len(), bytes.len());
+        assert_eq!(v1.bytes(), bytes);
+        assert_eq!(v1.content_hash(), response.content_hash());
+
+        // a version 0 request gets version 0 back
+        let request = request.with_version(0).unwrap();
+        let mut bytes = Vec::new();
+        response
+            .write_for(&request, Uuid::nil(), &mut bytes)
+            .unwrap();
+        crate::test::test_bytes(DATA_REAL, &bytes).unwrap();
+
+        // version 1 has room for more items than version 0
+        let builder = || {
+            ResponseBuilder::default().items(
+                (0..256)
+                    .map(|_| Item::new("frog".into(), ItemKind::Image, vec![]))
+                    .collect(),
+            )
+        };
+        assert!(builder().version(0).build().is_err());
+
+        let response = builder().build().unwrap();
+        let mut bytes = Vec::new();
+        assert!(response
+            .write_for(&request, Uuid::nil(), &mut bytes)
+            .is_err());
+
+        let limits = DecodeLimits {
+            max_items: 256,
+            ..DecodeLimits::default()
+        };
+        let response =
+            Response::from_bytes_with_limits(&mut response.bytes().as_slice(), limits).unwrap();
+        assert_eq!(response.items().len(), 256);
+    }
+
+    #[test]
+    fn item_kinds() {
+        // items the server didn't give a media type have their kind's
+        let mut bytes = DATA_REAL;
+        let response = Response::from_bytes(&mut bytes).unwrap();
+        assert!(!response.items()[0].has_media_type());
+        assert_eq!(response.items()[0].media_type(), "image/*");
+
+        let item = Item::sniffed("pond.css".into(), b"body {}".to_vec());
+        assert_eq!(item.kind(), ItemKind::Stylesheet);
+        assert_eq!(item.media_type(), "text/css");
+
+        // the data isn't looked at again
+        let item = Item::new("frog.ogg".into(), ItemKind::Video, b"OggS\0".to_vec());
+        assert_eq!(item.media_type(), "video/*");
+        let item = Item::elided("frog.png".into(), ItemKind::Image, ContentHash::of(b"frog"));
+        assert_eq!(item.media_type(), "image/*");
+
+        // media types go on the wire in every version, even for formats that can't be sniffed
+        let response = ResponseBuilder::default()
+            .item(Item::sniffed(
+                "white.png".into(),
+                include_bytes!("../1px_white.png").to_vec(),
+            ))
+            .item(
+                Item::new("main.rs".into(), ItemKind::Text, b"fn main() {}".to_vec())
+                    .with_media_type("text/x-rust"),
+            )
+            .item(Item::new("frog".into(), ItemKind::Binary, b"frog".to_vec()))
+            .build()
+            .unwrap();
+        for &version in &[0, 1] {
+            let request = Request::new("index.fml", RequestKind::PageItems)
+                .unwrap()
+                .with_version(version)
+                .unwrap();
+            let mut bytes = Vec::new();
+            response
+                .write_for(&request, Uuid::nil(), &mut bytes)
+                .unwrap();
+            assert_ne!(
+                bytes[REQUEST_RESPONSE_KIND_OFFSET] & RESPONSE_FLAG_MEDIA_TYPES,
+                0
+            );
+
+            let read = Response::from_bytes(&mut bytes.as_slice()).unwrap();
+            assert_eq!(read.encoded_len(), bytes.len());
+            assert_eq!(read.items()[0].media_type(), "image/png");
+            assert_eq!(read.items()[1].media_type(), "text/x-rust");
+            assert!(!read.items()[2].has_media_type());
+            assert_eq!(read.content_hash(), response.content_hash());
+        }
+
+        // metadata responses keep them too
+        let request = Request::new("index.fml", RequestKind::Metadata).unwrap();
+        let mut bytes = Vec::new();
+        response
+            .write_for(&request, Uuid::nil(), &mut bytes)
+            .unwrap();
+        let read = Response::from_bytes(&mut bytes.as_slice()).unwrap();
+        assert_eq!(read.items()[1].media_type(), "text/x-rust");
+
+        assert_eq!(u8::from(ItemKind::Binary), 6);
+        assert_eq!(ItemKind::from(5), ItemKind::Stylesheet);
+    }
+
+    #[test]
+    fn compressed() {
+        let mut bytes = DATA_REAL;
+        let response = Response::from_bytes(&mut bytes).unwrap();
+        let response = ResponseBuilder::default()
+            .page(response.page().repeat(50))
+            .items(
+                response
+                    .items()
+                    .iter()
+                    .map(|item| Item::new(item.name().into(), item.kind(), item.data().to_vec()))
+                    .collect(),
+            )
+            .build()
+            .unwrap();
+
+        let request = Request::new("index.fml", RequestKind::PageItems)
+            .unwrap()
+            .with_version(1)
+            .unwrap()
+            .with_accepted_compression(&[Compression::Unknown(9), Compression::Deflate])
+            .unwrap();
+        let mut bytes = Vec::new();
+        response
+            .write_for(&request, Uuid::nil(), &mut bytes)
+            .unwrap();
+
+        // the page shrinks, but the pixels are already as small as they get
+        let flags = bytes[REQUEST_RESPONSE_KIND_OFFSET] & !RESPONSE_KIND_MASK;
+        if cfg!(feature = "compression") {
+            assert_eq!(flags, RESPONSE_FLAG_COMPRESSED);
+            assert!(bytes.len() < response.encoded_len() / 2);
+
+            let mut reader = ResponseReader::new(bytes.as_slice()).unwrap();
+            assert_eq!(reader.page(), response.page());
+            let white = reader.next_item().unwrap().unwrap();
+            assert_eq!(white.compression(), Compression::None);
+        } else {
+            assert_eq!(flags, 0);
+        }
+
+        let read = Response::from_bytes(&mut bytes.as_slice()).unwrap();
+        assert_eq!(read.page(), response.page());
+        assert_eq!(read.content_hash(), response.content_hash());
+        assert_eq!(read.bytes(), response.bytes());
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn compressed_items() {
+        let text = "ribbit ".repeat(100);
+        let response = ResponseBuilder::default()
+            .item(Item::sniffed("frog.txt".into(), text.clone().into_bytes()))
+            .item(Item::sniffed(
+                "toad.txt".into(),
+                text.replace("r", "cr").into_bytes(),
+            ))
+            .build()
+            .unwrap();
+        let toad = response.items()[1].hash();
+
+        // the client has the toad, and wants zstd
+        let request = Request::new("index.fml", RequestKind::PageItems)
+            .unwrap()
+            .with_accepted_compression(&[Compression::Zstd])
+            .unwrap()
+            .with_known_items(vec![toad])
+            .unwrap();
+        let mut bytes = Vec::new();
+        response
+            .write_for(&request, Uuid::nil(), &mut bytes)
+            .unwrap();
+
+        let mut reader = ResponseReader::new(bytes.as_slice()).unwrap();
+        let frog = reader.next_item().unwrap().unwrap();
+        assert_eq!(frog.compression(), Compression::Zstd);
+        assert!(frog.len() < text.len());
+        assert_eq!(frog.into_item().unwrap().data(), text.as_bytes());
+        let toad = reader.next_item().unwrap().unwrap();
+        assert_eq!(toad.compression(), Compression::None);
+        assert!(toad.is_elided());
+
+        // decompressing is limited like anything else
+        let limits = DecodeLimits {
+            max_item_size: text.len() - 1,
+            ..DecodeLimits::default()
+        };
+        assert!(matches!(
+            Response::from_bytes_with_limits(&mut bytes.as_slice(), limits)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::LimitError {
+                limit: Limit::ItemSize,
+                ..
+            }
+        ));
+
+        // including the total length, once the data is decompressed
+        let limits = DecodeLimits {
+            max_total: bytes.len() + text.len() / 2,
+            ..DecodeLimits::default()
+        };
+        assert!(matches!(
+            Response::from_bytes_with_limits(&mut bytes.as_slice(), limits)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::LimitError {
+                limit: Limit::Total,
+                ..
+            }
+        ));
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn cached_response() {
+        let text = "ribbit ".repeat(100);
+        let response = ResponseBuilder::default()
+            .page(text.repeat(2))
+            .item(Item::new(
+                "frog.txt".into(),
+                ItemKind::Text,
+                text.clone().into_bytes(),
+            ))
+            .item(Item::new(
+                "frog.png".into(),
+                ItemKind::Image,
+                text.clone().into_bytes(),
+            ))
+            .build()
+            .unwrap();
+        let cached =
+            CachedResponse::new(Response::from_bytes(&mut response.bytes().as_slice()).unwrap())
+                .unwrap();
+
+        // the same bytes as compressing on the spot, for every codec and none at all
+        for accepted in [vec![Compression::Zstd], vec![Compression::Deflate], vec![]].iter() {
+            let request = Request::new("index.fml", RequestKind::PageItems)
+                .unwrap()
+                .with_accepted_compression(accepted)
+                .unwrap();
+
+            let (mut fresh, mut reused) = (Vec::new(), Vec::new());
+            response
+                .write_for(&request, Uuid::nil(), &mut fresh)
+                .unwrap();
+            cached
+                .write_for(&request, Uuid::nil(), &mut reused)
+                .unwrap();
+            assert_eq!(fresh, reused);
+
+            // images aren't worth compressing, even when they'd get smaller
+            let mut reader = ResponseReader::new(reused.as_slice()).unwrap();
+            let expected = accepted.first().copied().unwrap_or(Compression::None);
+            assert_eq!(reader.next_item().unwrap().unwrap().compression(), expected);
+            assert_eq!(
+                reader.next_item().unwrap().unwrap().compression(),
+                Compression::None
+            );
+        }
+    }
+
+    #[test]
+    fn response_reader() {
+        let mut bytes = DATA_REAL;
+        let mut reader = ResponseReader::new(&mut bytes).unwrap();
+        assert!(matches!(reader.kind(), ResponseKind::Page));
+        assert!(reader.page().starts_with(r#"(img "white.png")"#));
+        assert_eq!(reader.items_left(), 2);
+
+        // skip white.png without reading any of it
+        let white = reader.next_item().unwrap().unwrap();
+        assert_eq!(white.name(), "white.png");
+        assert_eq!(white.len(), 119);
+
+        let mut magenta = reader.next_item().unwrap().unwrap();
+        assert_eq!(magenta.name(), "magenta.png");
+
+        let mut data = Vec::new();
+        magenta.read_to_end(&mut data).unwrap();
+        assert_eq!(&data, include_bytes!("../1px_magenta.png"));
+
+        assert!(reader.next_item().unwrap().is_none());
+        assert!(bytes.is_empty());
+    }
+
+    #[test]
+    fn response_reader_cut_short() {
+        let mut bytes = &DATA_REAL[..DATA_REAL.len() - 10];
+        let mut reader = ResponseReader::new(&mut bytes).unwrap();
+        reader.next_item().unwrap().unwrap();
+        assert!(reader.next_item().unwrap().unwrap().into_item().is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let hash = ContentHash::of(b"known");
+        let response = ResponseBuilder::default()
+            .page("(\"hi\")".into())
+            .item(
+                Item::new("frog".into(), ItemKind::Image, b"frog".to_vec())
+                    .with_media_type("image/x-frog"),
+            )
+            .item(Item::new("empty".into(), ItemKind::Unknown(15), Vec::new()))
+            .item(Item::elided("known".into(), ItemKind::Image, hash))
+            .item(Item::described("big".into(), ItemKind::Video, hash, 4096))
+            .build()
+            .unwrap();
+
+        // item data is written as base64, and elided items have none
+        let mut json = serde_json::to_value(&response).unwrap();
+        assert_eq!(json["items"][0]["data"], "ZnJvZw==");
+        assert_eq!(
+            json["items"][0]["hash"],
+            ContentHash::of(b"frog").to_string()
+        );
+        assert!(json["items"][1].get("data").is_none());
+        assert!(json["items"][2].get("data").is_none());
+        assert_eq!(json["items"][0]["media_type"], "image/x-frog");
+        assert!(json["items"][1].get("media_type").is_none());
+
+        let round_trip: Response = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(round_trip.page(), response.page());
+        assert_eq!(round_trip.items()[0].media_type(), "image/x-frog");
+        assert_eq!(round_trip.content_hash(), response.content_hash());
+        assert!(!round_trip.items()[1].is_elided());
+        assert!(round_trip.items()[2].is_elided());
+        assert_eq!(round_trip.items()[2].hash(), hash);
+        assert_eq!(round_trip.items()[2].size(), None);
+        assert_eq!(round_trip.items()[3].size(), Some(4096));
+
+        // hashes can be left out, but they have to match if they're there
+        json["items"][0].as_object_mut().unwrap().remove("hash");
+        let round_trip: Response = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(round_trip.items()[0].data(), b"frog");
+        json["items"][0]["hash"] = hash.to_string().into();
+        assert!(serde_json::from_value::<Response>(json).is_err());
+    }
+
+    #[test]
+    fn elided_items() {
+        let mut bytes = DATA_REAL;
+        let response = Response::from_bytes(&mut bytes).unwrap();
+        let white = response.items()[0].hash();
+
+        // the client already has the white pixel
+        let mut bytes = Vec::new();
+        response
+            .write_to_eliding(Uuid::nil(), &[white], &mut bytes)
+            .unwrap();
+        assert_eq!(
+            bytes.len(),
+            DATA_REAL.len() + 2 * CONTENT_HASH_LEN - response.items()[0].data().len()
+        );
+
+        let mut elided = Response::from_bytes(&mut bytes.as_slice()).unwrap();
+        assert!(elided.items()[0].is_elided());
+        assert!(elided.items()[0].data().is_empty());
+        assert!(!elided.items()[1].is_elided());
+        assert_eq!(elided.content_hash(), response.content_hash());
+
+        // and it survives being written again
+        let mut again = Response::from_bytes(&mut elided.bytes().as_slice()).unwrap();
+        assert!(again.items()[0].is_elided());
+
+        // the items can only be put back with the white pixel in the store
+        let mut store = crate::store::MemoryStore::new();
+        assert!(matches!(
+            elided.fill_items(&store).unwrap_err().kind(),
+            ErrorKind::MissingItemError { name, .. } if name == "white.png"
+        ));
+
+        response.remember_items("example.com/index.fml", &mut store);
+        assert_eq!(store.page_hashes("example.com/index.fml").len(), 2);
+        elided.fill_items(&store).unwrap();
+        again.fill_items(&store).unwrap();
+        crate::test::test_bytes(DATA_REAL, &elided.bytes()).unwrap();
+        crate::test::test_bytes(DATA_REAL, &again.bytes()).unwrap();
+    }
+
+    #[test]
+    fn metadata() {
+        let mut bytes = DATA_REAL;
+        let response = Response::from_bytes(&mut bytes).unwrap();
+
+        // nothing is compressed, since there's nothing to compress
+        let request = Request::new("index.fml", RequestKind::Metadata)
+            .unwrap()
+            .with_version(1)
+            .unwrap()
+            .with_accepted_compression(&[Compression::Deflate])
+            .unwrap();
+        let mut bytes = Vec::new();
+        response
+            .write_for(&request, Uuid::nil(), &mut bytes)
+            .unwrap();
+        assert_eq!(
+            bytes[REQUEST_RESPONSE_KIND_OFFSET],
+            u8::from(ResponseKind::Page) | RESPONSE_FLAG_ITEM_HASHES | RESPONSE_FLAG_METADATA
+        );
+        assert!(bytes.len() < response.page().len() + 2 * CONTENT_HASH_LEN);
+
+        let metadata = Response::from_bytes(&mut bytes.as_slice()).unwrap();
+        assert!(metadata.is_metadata());
+        assert_eq!(metadata.kind(), ResponseKind::Page);
+        assert_eq!(metadata.page(), "");
+        assert_eq!(metadata.page_len(), response.page().len());
+        for (described, item) in metadata.items().iter().zip(response.items()) {
+            assert_eq!(described.name(), item.name());
+            assert_eq!(described.hash(), item.hash());
+            assert_eq!(described.size(), Some(item.data().len()));
+            assert!(described.is_elided());
+        }
+
+        // built by hand, it's the same, and it stays that way when it's written again
+        let built = ResponseBuilder::default()
+            .page(response.page().into())
+            .items(
+                response
+                    .items()
+                    .iter()
+                    .map(|item| Item::new(item.name().into(), item.kind(), item.data().to_vec()))
+                    .collect(),
+            )
+            .metadata()
+            .build()
+            .unwrap();
+        assert_eq!(built.bytes(), bytes);
+        assert_eq!(metadata.bytes(), bytes);
+        assert_eq!(metadata.encoded_len(), bytes.len());
+
+        // the lengths are only descriptions, so they aren't limited
+        let limits = DecodeLimits {
+            max_page: 1,
+            max_item_size: 1,
+            ..DecodeLimits::default()
+        };
+        Response::from_bytes_with_limits(&mut bytes.as_slice(), limits).unwrap();
+
+        // redirects are written whole
+        let redirect = ResponseBuilder::default()
+            .redirect("elsewhere.fml")
+            .build()
+            .unwrap();
+        let mut bytes = Vec::new();
+        redirect
+            .write_for(&request, Uuid::nil(), &mut bytes)
+            .unwrap();
+        let redirect = Response::from_bytes(&mut bytes.as_slice()).unwrap();
+        assert!(!redirect.is_metadata());
+        assert_eq!(redirect.redirect_target(), Some("elsewhere.fml"));
+    }
+
+    #[test]
+    fn item_range() {
+        let mut bytes = DATA_REAL;
+        let response = Response::from_bytes(&mut bytes).unwrap();
+        let white = &response.items()[0];
+
+        let range = ResponseBuilder::default()
+            .version(0)
+            .item_range(white, 100, Some(10))
+            .build()
+            .unwrap();
+        assert_eq!(range.items()[0].data(), &white.data()[100..110]);
+        assert_eq!(
+            range.item_range(),
+            Some(ItemRange {
+                offset: 100,
+                size: 119
+            })
+        );
+
+        // the offset and size come after the total length, like an error code
+        let bytes = range.bytes();
+        assert_eq!(bytes.len(), range.encoded_len());
+        assert_eq!(
+            &bytes[PAGE_LENGTH_OFFSET..PAGE_LENGTH_OFFSET + 8],
+            &[100, 0, 0, 0, 119, 0, 0, 0]
+        );
+
+        let read = Response::from_bytes(&mut bytes.as_slice()).unwrap();
+        assert_eq!(read.kind(), ResponseKind::ItemRange);
+        assert_eq!(read.item_range(), range.item_range());
+        assert_eq!(read.items()[0].name(), "white.png");
+        assert_eq!(read.items()[0].data(), range.items()[0].data());
+
+        // ranges are cut short at the end of the item
+        let range = ResponseBuilder::default()
+            .item_range(white, 110, Some(100))
+            .build()
+            .unwrap();
+        let read = Response::from_bytes(&mut range.bytes().as_slice()).unwrap();
+        assert_eq!(read.items()[0].data(), &white.data()[110..]);
+        assert_eq!(read.item_range(), range.item_range());
+
+        // and they can start at the end, but not past it
+        let range = ResponseBuilder::default()
+            .item_range(white, 119, None)
+            .build()
+            .unwrap();
+        assert!(range.items()[0].data().is_empty());
+        assert!(ResponseBuilder::default()
+            .item_range(white, 120, None)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn item_hash_mismatch() {
+        let mut bytes = DATA_REAL;
+        let response = Response::from_bytes(&mut bytes).unwrap();
+
+        let mut bytes = Vec::new();
+        response
+            .write_to_eliding(Uuid::nil(), &[], &mut bytes)
+            .unwrap();
+        assert_eq!(
+            bytes[REQUEST_RESPONSE_KIND_OFFSET],
+            u8::from(ResponseKind::Page) | RESPONSE_FLAG_ITEM_HASHES
+        );
+        Response::from_bytes(&mut bytes.as_slice()).unwrap();
+
+        // scribble on the end of the magenta pixel
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        assert!(matches!(
+            Response::from_bytes(&mut bytes.as_slice())
+                .unwrap_err()
+                .kind(),
+            ErrorKind::ResponseFormatError
+        ));
+
+        // unknown flags
+        let mut bytes = DATA_REAL.to_vec();
+        bytes[REQUEST_RESPONSE_KIND_OFFSET] |= 0x80;
+        assert!(Response::from_bytes(&mut bytes.as_slice()).is_err());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_version_1() {
+        let response = ResponseBuilder::default()
+            .page("(\"hi\")".into())
+            .item(Item::new("frog".into(), ItemKind::Image, b"frog".to_vec()))
+            .build()
+            .unwrap();
+
+        let bytes = response.bytes();
+        let read = Response::from_bytes_async(&mut bytes.as_slice())
+            .await
+            .unwrap();
+        assert_eq!(read.bytes(), bytes);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_round_trip() {
+        let mut bytes = DATA_REAL;
+        let response = Response::from_bytes_async(&mut bytes).await.unwrap();
+        assert_eq!(&response.items[1].name, "magenta.png");
+
+        let mut data_test = Vec::new();
+        response.write_to_async(&mut data_test).await.unwrap();
+
+        assert_eq!(data_test.len(), DATA_REAL.len());
+        crate::test::test_bytes(DATA_REAL, &data_test).unwrap();
+    }
 }
@@ -0,0 +1,102 @@
+//! Local storage for items, so they don't have to be sent again.
+//!
+//! Clients list the hashes of the items they have in requests, and the server leaves the data of
+//! those items out of its response. The items are then put back together from the store.
+//!
+//! Only the items a page referenced the last time it was requested are listed in requests for
+//! it, so servers don't get to see everything else the client has been looking at.
+
+use crate::hash::ContentHash;
+
+use std::collections::HashMap;
+
+/// The most known items to list in a request, which is as many as servers accept by default.
+pub const MAX_KNOWN_ITEMS: usize = 1024;
+
+/// Somewhere to keep item data, looked up by content hash.
+pub trait ItemStore {
+    /// Get the data with this hash, if it's stored
+    fn get(&self, hash: &ContentHash) -> Option<&[u8]>;
+
+    /// Store some data under its hash
+    fn insert(&mut self, hash: ContentHash, data: &[u8]);
+
+    /// Remember which items a page referenced. `page` identifies the server and page together
+    fn insert_page(&mut self, page: &str, hashes: Vec<ContentHash>);
+
+    /// Get the hashes of the stored items that a page referenced, at most `MAX_KNOWN_ITEMS`
+    fn page_hashes(&self, page: &str) -> Vec<ContentHash>;
+}
+
+/// An item store that keeps everything in memory.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryStore {
+    items: HashMap<ContentHash, Vec<u8>>,
+    pages: HashMap<String, Vec<ContentHash>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+}
+
+impl ItemStore for MemoryStore {
+    fn get(&self, hash: &ContentHash) -> Option<&[u8]> {
+        self.items.get(hash).map(Vec::as_slice)
+    }
+
+    fn insert(&mut self, hash: ContentHash, data: &[u8]) {
+        self.items.entry(hash).or_insert_with(|| data.to_vec());
+    }
+
+    fn insert_page(&mut self, page: &str, hashes: Vec<ContentHash>) {
+        self.pages.insert(page.into(), hashes);
+    }
+
+    fn page_hashes(&self, page: &str) -> Vec<ContentHash> {
+        self.pages
+            .get(page)
+            .into_iter()
+            .flatten()
+            .filter(|hash| self.items.contains_key(hash))
+            .take(MAX_KNOWN_ITEMS)
+            .copied()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn page_hashes() {
+        let frog = ContentHash::of(b"frog");
+        let toad = ContentHash::of(b"toad");
+        let newt = ContentHash::of(b"newt");
+
+        let mut store = MemoryStore::new();
+        store.insert(frog, b"frog");
+        store.insert(toad, b"toad");
+        store.insert_page("example.com/pond.fml", vec![frog, newt]);
+
+        // only what the page referenced and we still have
+        assert_eq!(store.page_hashes("example.com/pond.fml"), vec![frog]);
+        assert!(store.page_hashes("example.com/other.fml").is_empty());
+        assert!(store.page_hashes("other.com/pond.fml").is_empty());
+
+        let many = (0..MAX_KNOWN_ITEMS as u32 + 10)
+            .map(|i| {
+                let hash = ContentHash::of(&i.to_le_bytes());
+                store.insert(hash, &i.to_le_bytes());
+                hash
+            })
+            .collect();
+        store.insert_page("example.com/big.fml", many);
+        assert_eq!(
+            store.page_hashes("example.com/big.fml").len(),
+            MAX_KNOWN_ITEMS
+        );
+    }
+}
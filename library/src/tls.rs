@@ -0,0 +1,360 @@
+//! TLS transport for the froggi protocol, also known as frgis.
+//!
+//! Requests and responses are exactly the same as over plain TCP, they're just wrapped in a
+//! rustls stream. Clients can either trust a root store, or pin the exact certificate the server
+//! is expected to present, which is useful for self-signed certificates.
+
+use crate::redirect::RedirectPolicy;
+use crate::request::{Request, RequestKind};
+use crate::response::Response;
+use crate::url::Url;
+use crate::{AddMsg, ErrorKind, FroggiError, Uuid};
+
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{self, CryptoProvider};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{
+    CertificateError, ClientConfig, ClientConnection, DigitallySignedStruct, RootCertStore,
+    ServerConfig, SignatureScheme, StreamOwned,
+};
+
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::Arc;
+
+pub use rustls;
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(crypto::ring::default_provider())
+}
+
+/// Create a client configuration that trusts servers whose certificates chain up to the roots.
+pub fn client_config(roots: RootCertStore) -> Result<Arc<ClientConfig>, FroggiError> {
+    Ok(Arc::new(
+        ClientConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots)
+            .with_no_client_auth(),
+    ))
+}
+
+/// Create a client configuration that only trusts a server presenting exactly this certificate.
+///
+/// The certificate's issuer, validity period, and names are not checked. The server still has to
+/// prove that it holds the certificate's private key.
+pub fn pinned_client_config(
+    certificate: CertificateDer<'static>,
+) -> Result<Arc<ClientConfig>, FroggiError> {
+    let provider = provider();
+    let verifier = PinnedCertificate {
+        certificate,
+        provider: provider.clone(),
+    };
+
+    Ok(Arc::new(
+        ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(verifier))
+            .with_no_client_auth(),
+    ))
+}
+
+/// Create a server configuration from a certificate chain and its private key.
+pub fn server_config(
+    chain: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+) -> Result<Arc<ServerConfig>, FroggiError> {
+    Ok(Arc::new(
+        ServerConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_single_cert(chain, key)?,
+    ))
+}
+
+/// Read every certificate from a PEM file.
+pub fn load_certificates(
+    path: impl AsRef<Path>,
+) -> Result<Vec<CertificateDer<'static>>, FroggiError> {
+    let path = path.as_ref();
+    CertificateDer::pem_file_iter(path)
+        .and_then(|certificates| certificates.collect())
+        .map_err(|error| pem_error(error, path))
+}
+
+/// Read the first private key from a PEM file.
+pub fn load_private_key(path: impl AsRef<Path>) -> Result<PrivateKeyDer<'static>, FroggiError> {
+    let path = path.as_ref();
+    PrivateKeyDer::from_pem_file(path).map_err(|error| pem_error(error, path))
+}
+
+fn pem_error(error: rustls::pki_types::pem::Error, path: &Path) -> FroggiError {
+    FroggiError::io(io::Error::new(
+        io::ErrorKind::InvalidData,
+        error.to_string(),
+    ))
+    .msg(format!("could not read PEM file {}", path.display()))
+}
+
+/// Send a froggi request to a server over TLS and return its response.
+///
+/// Error responses from the server are returned as `ErrorKind::StatusError`.
+pub fn send_request(
+    to: impl ToSocketAddrs,
+    server_name: &str,
+    config: Arc<ClientConfig>,
+    request: &str,
+    kind: RequestKind,
+) -> Result<Response, FroggiError> {
+    send_request_with_id(to, server_name, config, request, Uuid::nil(), kind)
+}
+
+/// Send a froggi request for a URL over TLS and return its response.
+///
+/// The URL's host is used as the server name. `frgi://` URLs are also sent over TLS.
+pub fn send_request_to_url(
+    url: &Url,
+    config: Arc<ClientConfig>,
+    kind: RequestKind,
+) -> Result<Response, FroggiError> {
+    send_request_to_url_with_id(url, config, Uuid::nil(), kind)
+}
+
+/// Send a froggi request for a URL over TLS with a client ID and return its response.
+pub fn send_request_to_url_with_id(
+    url: &Url,
+    config: Arc<ClientConfig>,
+    id: Uuid,
+    kind: RequestKind,
+) -> Result<Response, FroggiError> {
+    let (host, port) = url.socket_addr();
+    send_request_with_id((host, port), host, config, url.request(), id, kind)
+}
+
+/// Send a froggi request for a URL over TLS with a client ID, following redirects.
+///
+/// Returns the URL the final response came from along with the response.
+pub fn send_request_following_redirects(
+    url: &Url,
+    config: Arc<ClientConfig>,
+    id: Uuid,
+    kind: RequestKind,
+    policy: RedirectPolicy,
+) -> Result<(Url, Response), FroggiError> {
+    crate::redirect::follow(url, id, policy, |url, id| {
+        send_request_to_url_with_id(url, config.clone(), id, kind)
+    })
+}
+
+/// Send a froggi request to a server over TLS with a client ID and return its response.
+pub fn send_request_with_id(
+    to: impl ToSocketAddrs,
+    server_name: &str,
+    config: Arc<ClientConfig>,
+    request: &str,
+    id: Uuid,
+    kind: RequestKind,
+) -> Result<Response, FroggiError> {
+    let mut stream = client_stream(TcpStream::connect(to)?, server_name, config)?;
+
+    Request::new_with_id(request, id, kind)?.write_to(&mut stream)?;
+    stream.flush()?;
+
+    Response::from_bytes(&mut stream)?.error_for_status()
+}
+
+// start a TLS session with a server we're connected to
+pub(crate) fn client_stream<S: Read + Write>(
+    stream: S,
+    server_name: &str,
+    config: Arc<ClientConfig>,
+) -> Result<StreamOwned<ClientConnection, S>, FroggiError> {
+    let server_name = ServerName::try_from(server_name.to_string()).map_err(|_| {
+        FroggiError::new(ErrorKind::TlsError {
+            error: rustls::Error::General(format!("invalid server name {}", server_name)),
+        })
+    })?;
+
+    let connection = ClientConnection::new(config, server_name)?;
+    Ok(StreamOwned::new(connection, stream))
+}
+
+#[derive(Debug)]
+struct PinnedCertificate {
+    certificate: CertificateDer<'static>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedCertificate {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if end_entity.as_ref() == self.certificate.as_ref() {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::InvalidCertificate(
+                CertificateError::ApplicationVerificationFailure,
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::response::ResponseBuilder;
+
+    use rustls::pki_types::PrivatePkcs8KeyDer;
+    use rustls::ServerConnection;
+
+    use std::net::{SocketAddr, TcpListener};
+    use std::thread::JoinHandle;
+
+    fn self_signed() -> (CertificateDer<'static>, PrivateKeyDer<'static>) {
+        let certified =
+            rcgen::generate_simple_self_signed(vec![String::from("localhost")]).unwrap();
+        let certificate = certified.cert.der().clone();
+        let key = PrivatePkcs8KeyDer::from(certified.signing_key.serialize_der()).into();
+        (certificate, key)
+    }
+
+    // serve a single request over TLS
+    fn serve_once(config: Arc<ServerConfig>) -> (SocketAddr, JoinHandle<Result<(), FroggiError>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let handle = std::thread::spawn(move || {
+            let (tcp, _) = listener.accept()?;
+            let mut stream = StreamOwned::new(ServerConnection::new(config)?, tcp);
+
+            let request = Request::from_bytes(&mut stream)?;
+            ResponseBuilder::default()
+                .page(format!("('you asked for {}')", request.request()))
+                .build()?
+                .write_to(&mut stream)?;
+            stream.flush()?;
+
+            Ok(())
+        });
+
+        (addr, handle)
+    }
+
+    #[test]
+    fn pinned_certificate() {
+        let (certificate, key) = self_signed();
+        let (addr, handle) = serve_once(server_config(vec![certificate.clone()], key).unwrap());
+
+        let response = send_request(
+            addr,
+            "localhost",
+            pinned_client_config(certificate).unwrap(),
+            "index.fml",
+            RequestKind::PageOnly,
+        )
+        .unwrap();
+
+        assert_eq!(response.page(), "('you asked for index.fml')");
+        handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn url() {
+        let (certificate, key) = self_signed();
+        let (addr, handle) = serve_once(server_config(vec![certificate.clone()], key).unwrap());
+
+        let url = Url::parse(&format!("frgis://localhost:{}/index.fml", addr.port())).unwrap();
+        let response = send_request_to_url(
+            &url,
+            pinned_client_config(certificate).unwrap(),
+            RequestKind::Page,
+        )
+        .unwrap();
+
+        assert_eq!(response.page(), "('you asked for index.fml')");
+        handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn root_store() {
+        let (certificate, key) = self_signed();
+        let (addr, handle) = serve_once(server_config(vec![certificate.clone()], key).unwrap());
+
+        let mut roots = RootCertStore::empty();
+        roots.add(certificate).unwrap();
+
+        let response = send_request(
+            addr,
+            "localhost",
+            client_config(roots).unwrap(),
+            "index.fml",
+            RequestKind::PageOnly,
+        )
+        .unwrap();
+
+        assert_eq!(response.page(), "('you asked for index.fml')");
+        handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn wrong_pinned_certificate() {
+        let (certificate, key) = self_signed();
+        let (other, _) = self_signed();
+        let (addr, handle) = serve_once(server_config(vec![certificate], key).unwrap());
+
+        let error = send_request(
+            addr,
+            "localhost",
+            pinned_client_config(other).unwrap(),
+            "index.fml",
+            RequestKind::PageOnly,
+        )
+        .unwrap_err();
+
+        assert!(matches!(error.kind(), ErrorKind::IOError { .. }));
+        assert!(handle.join().unwrap().is_err());
+    }
+}
@@ -0,0 +1,401 @@
+//! Ways of carrying requests and responses between clients and servers.
+//!
+//! Froggi doesn't care what the bytes travel over, as long as it's a reliable stream. Clients
+//! open streams with a `Transport`, and servers wait for them with a `Listener`. TCP is what
+//! froggi normally runs over, Unix domain sockets are handy for local servers, and in-memory
+//! pipes let a client and server talk to each other in the same process.
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::PathBuf;
+
+/// A connection between a client and server.
+pub trait Stream: Read + Write + Send + 'static {
+    /// Give up on reading or writing after some time without progress. `None` waits forever.
+    fn set_timeouts(&self, read: Option<Duration>, write: Option<Duration>) -> io::Result<()>;
+}
+
+/// Opens connections to servers.
+pub trait Transport {
+    /// The kind of connection this transport opens
+    type Stream: Stream;
+
+    /// Open a connection to the server at a host and port
+    fn connect(&self, host: &str, port: u16) -> io::Result<Self::Stream>;
+}
+
+/// Waits for connections from clients.
+pub trait Listener {
+    /// The kind of connection this listener accepts
+    type Stream: Stream;
+
+    /// Wait for the next client to connect
+    fn accept(&self) -> io::Result<Self::Stream>;
+}
+
+impl Stream for TcpStream {
+    fn set_timeouts(&self, read: Option<Duration>, write: Option<Duration>) -> io::Result<()> {
+        self.set_read_timeout(read)?;
+        self.set_write_timeout(write)
+    }
+}
+
+impl Listener for TcpListener {
+    type Stream = TcpStream;
+
+    fn accept(&self) -> io::Result<TcpStream> {
+        TcpListener::accept(self).map(|(stream, _)| stream)
+    }
+}
+
+/// Plain TCP, which connects to every address the host resolves to until one works.
+#[derive(Debug, Clone, Default)]
+pub struct Tcp {
+    connect_timeout: Option<Duration>,
+}
+
+impl Tcp {
+    /// Create a TCP transport that waits forever for connections
+    pub fn new() -> Tcp {
+        Tcp::default()
+    }
+
+    /// Give up on connecting to an address after this long
+    pub fn with_connect_timeout(self, connect_timeout: Option<Duration>) -> Tcp {
+        Tcp { connect_timeout }
+    }
+
+    fn connect_to(&self, addr: SocketAddr) -> io::Result<TcpStream> {
+        match self.connect_timeout {
+            Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+            None => TcpStream::connect(addr),
+        }
+    }
+}
+
+impl Transport for Tcp {
+    type Stream = TcpStream;
+
+    fn connect(&self, host: &str, port: u16) -> io::Result<TcpStream> {
+        let mut last_error = None;
+
+        for addr in (host, port).to_socket_addrs()? {
+            match self.connect_to(addr) {
+                Ok(stream) => return Ok(stream),
+                Err(error) => last_error = Some(error),
+            }
+        }
+
+        Err(last_error
+            .unwrap_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no addresses for host")))
+    }
+}
+
+#[cfg(unix)]
+impl Stream for UnixStream {
+    fn set_timeouts(&self, read: Option<Duration>, write: Option<Duration>) -> io::Result<()> {
+        self.set_read_timeout(read)?;
+        self.set_write_timeout(write)
+    }
+}
+
+#[cfg(unix)]
+impl Listener for UnixListener {
+    type Stream = UnixStream;
+
+    fn accept(&self) -> io::Result<UnixStream> {
+        UnixListener::accept(self).map(|(stream, _)| stream)
+    }
+}
+
+/// A Unix domain socket. Every host and port connects to the same socket.
+#[cfg(unix)]
+#[derive(Debug, Clone)]
+pub struct Unix {
+    path: PathBuf,
+}
+
+#[cfg(unix)]
+impl Unix {
+    /// Create a transport that connects to the socket at a path
+    pub fn new(path: impl Into<PathBuf>) -> Unix {
+        Unix { path: path.into() }
+    }
+}
+
+#[cfg(unix)]
+impl Transport for Unix {
+    type Stream = UnixStream;
+
+    fn connect(&self, _host: &str, _port: u16) -> io::Result<UnixStream> {
+        UnixStream::connect(&self.path)
+    }
+}
+
+/// Create an in-memory transport, and a listener that accepts the connections it opens.
+///
+/// Every host and port connects to the same listener. Connections are refused once the listener
+/// is dropped.
+pub fn memory() -> (Memory, MemoryListener) {
+    let (connections, incoming) = mpsc::channel();
+    let incoming = Mutex::new(incoming);
+    (Memory { connections }, MemoryListener { incoming })
+}
+
+/// Opens in-memory connections to a `MemoryListener`.
+#[derive(Debug, Clone)]
+pub struct Memory {
+    connections: mpsc::Sender<MemoryStream>,
+}
+
+impl Transport for Memory {
+    type Stream = MemoryStream;
+
+    fn connect(&self, _host: &str, _port: u16) -> io::Result<MemoryStream> {
+        let (client, server) = MemoryStream::pair();
+        self.connections
+            .send(server)
+            .map_err(|_| io::Error::new(io::ErrorKind::ConnectionRefused, "listener is gone"))?;
+        Ok(client)
+    }
+}
+
+/// Accepts connections opened by a `Memory` transport.
+#[derive(Debug)]
+pub struct MemoryListener {
+    incoming: Mutex<mpsc::Receiver<MemoryStream>>,
+}
+
+impl Listener for MemoryListener {
+    type Stream = MemoryStream;
+
+    fn accept(&self) -> io::Result<MemoryStream> {
+        self.incoming.lock().unwrap().recv().map_err(|_| {
+            io::Error::new(io::ErrorKind::ConnectionAborted, "every transport is gone")
+        })
+    }
+}
+
+/// One end of an in-memory connection.
+///
+/// Dropping it closes the connection: the other end reads whatever is left and then nothing,
+/// and writes fail.
+#[derive(Debug)]
+pub struct MemoryStream {
+    read: Arc<Pipe>,
+    write: Arc<Pipe>,
+    read_timeout: Mutex<Option<Duration>>,
+}
+
+impl MemoryStream {
+    fn pair() -> (MemoryStream, MemoryStream) {
+        let (there, back) = (Arc::new(Pipe::default()), Arc::new(Pipe::default()));
+
+        let client = MemoryStream {
+            read: back.clone(),
+            write: there.clone(),
+            read_timeout: Mutex::new(None),
+        };
+
+        let server = MemoryStream {
+            read: there,
+            write: back,
+            read_timeout: Mutex::new(None),
+        };
+
+        (client, server)
+    }
+}
+
+impl Stream for MemoryStream {
+    // writes never wait, so there's no write timeout
+    fn set_timeouts(&self, read: Option<Duration>, _write: Option<Duration>) -> io::Result<()> {
+        // unwrap safety - we never panic while holding the lock
+        *self.read_timeout.lock().unwrap() = read;
+        Ok(())
+    }
+}
+
+impl Read for MemoryStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let timeout = *self.read_timeout.lock().unwrap();
+        self.read.read(buf, timeout)
+    }
+}
+
+impl Write for MemoryStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for MemoryStream {
+    fn drop(&mut self) {
+        self.read.close();
+        self.write.close();
+    }
+}
+
+// bytes going one way down an in-memory connection
+#[derive(Debug, Default)]
+struct Pipe {
+    state: Mutex<PipeState>,
+    readable: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct PipeState {
+    data: VecDeque<u8>,
+    closed: bool,
+}
+
+impl Pipe {
+    fn read(&self, buf: &mut [u8], timeout: Option<Duration>) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut state = self.state.lock().unwrap();
+
+        while state.data.is_empty() && !state.closed {
+            state = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(io::ErrorKind::TimedOut.into());
+                    }
+                    self.readable.wait_timeout(state, deadline - now).unwrap().0
+                }
+                None => self.readable.wait(state).unwrap(),
+            };
+        }
+
+        let len = std::cmp::min(buf.len(), state.data.len());
+        for (byte, data) in buf.iter_mut().zip(state.data.drain(..len)) {
+            *byte = data;
+        }
+
+        Ok(len)
+    }
+
+    fn write(&self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+
+        state.data.extend(buf);
+        self.readable.notify_all();
+        Ok(buf.len())
+    }
+
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.readable.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::request::{Request, RequestKind};
+    use crate::response::ResponseBuilder;
+    use crate::url::Url;
+    use crate::Client;
+
+    use std::thread;
+
+    // answer every request with its path
+    fn serve(listener: impl Listener + Send + 'static) -> thread::JoinHandle<usize> {
+        thread::spawn(move || {
+            let mut served = 0;
+            while let Ok(mut stream) = listener.accept() {
+                let request = Request::from_bytes(&mut stream).unwrap();
+                ResponseBuilder::default()
+                    .page(request.request().into())
+                    .build()
+                    .unwrap()
+                    .write_to(&mut stream)
+                    .unwrap();
+                served += 1;
+            }
+            served
+        })
+    }
+
+    #[test]
+    fn memory_pipe() {
+        let (transport, listener) = memory();
+        let server = serve(listener);
+
+        let client = Client::builder().transport(transport).build().unwrap();
+        let url = Url::parse("frgi://anywhere/a.fml").unwrap();
+        assert_eq!(
+            client.get(&url, RequestKind::PageOnly).unwrap().1.page(),
+            "a.fml"
+        );
+        let url = Url::parse("frgi://elsewhere/b.fml").unwrap();
+        assert_eq!(
+            client.get(&url, RequestKind::PageOnly).unwrap().1.page(),
+            "b.fml"
+        );
+
+        // the server stops once nobody can connect to it
+        drop(client);
+        assert_eq!(server.join().unwrap(), 2);
+    }
+
+    #[test]
+    fn memory_stream() {
+        let (mut client, mut server) = MemoryStream::pair();
+        client.write_all(b"frg").unwrap();
+        drop(client);
+
+        // whatever was written can still be read after the other end is gone
+        let mut data = Vec::new();
+        server.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"frg");
+        assert!(server.write_all(b"?").is_err());
+
+        let (_client, mut server) = MemoryStream::pair();
+        server
+            .set_timeouts(Some(Duration::from_millis(10)), None)
+            .unwrap();
+        assert_eq!(
+            server.read(&mut [0]).unwrap_err().kind(),
+            io::ErrorKind::TimedOut
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_socket() {
+        let path = std::env::temp_dir().join(format!("froggi-{}.sock", uuid::Uuid::new_v4()));
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = serve(listener);
+
+        let transport = Unix::new(&path);
+        let mut stream = transport.connect("", 0).unwrap();
+        Request::new("a.fml", RequestKind::PageOnly)
+            .unwrap()
+            .write_to(&mut stream)
+            .unwrap();
+        let response = crate::response::Response::from_bytes(&mut stream).unwrap();
+        assert_eq!(response.page(), "a.fml");
+
+        drop(server);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,376 @@
+//! Froggi URLs, like `frgi://example.com/test_markup.fml#anchor`.
+
+use crate::{ErrorKind, FroggiError, UrlError};
+
+use std::fmt;
+use std::str::FromStr;
+
+/// The port froggi servers listen on when the URL doesn't say otherwise.
+pub const DEFAULT_PORT: u16 = 11121;
+
+/// How to talk to the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scheme {
+    /// Plain TCP, `frgi://`
+    Frgi,
+    /// TLS, `frgis://`
+    Frgis,
+}
+
+impl Scheme {
+    /// Get the scheme as it appears in a URL
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scheme::Frgi => "frgi",
+            Scheme::Frgis => "frgis",
+        }
+    }
+}
+
+/// A froggi URL.
+///
+/// The path always starts with a `/`. Froggi URLs have no user info or query string.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Url {
+    scheme: Scheme,
+    host: String,
+    port: Option<u16>,
+    path: String,
+    fragment: Option<String>,
+}
+
+impl Url {
+    /// Parse an absolute URL.
+    pub fn parse(url: &str) -> Result<Url, FroggiError> {
+        let (scheme, rest) = url
+            .find("://")
+            .map(|i| (&url[..i], &url[i + 3..]))
+            .ok_or_else(|| url_error(UrlError::NotAbsolute { url: url.into() }))?;
+
+        let scheme = match scheme.to_ascii_lowercase().as_str() {
+            "frgi" => Scheme::Frgi,
+            "frgis" => Scheme::Frgis,
+            _ => {
+                return Err(url_error(UrlError::UnknownScheme {
+                    scheme: scheme.into(),
+                }))
+            }
+        };
+
+        let (rest, fragment) = split_fragment(rest);
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+
+        let (host, port) = split_port(authority)?;
+        if host.is_empty() {
+            return Err(url_error(UrlError::MissingHost { url: url.into() }));
+        }
+
+        Ok(Url {
+            scheme,
+            host: host.to_ascii_lowercase(),
+            port,
+            path: normalize_path(path),
+            fragment: fragment.map(String::from),
+        })
+    }
+
+    /// Get the scheme of the URL
+    pub fn scheme(&self) -> Scheme {
+        self.scheme
+    }
+
+    /// Get the host of the URL, without the port
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// Get the port of the URL, or the default port if it doesn't have one
+    pub fn port(&self) -> u16 {
+        self.port.unwrap_or(DEFAULT_PORT)
+    }
+
+    /// Get the path of the URL, starting with `/`
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Get the fragment of the URL, without the `#`
+    pub fn fragment(&self) -> Option<&str> {
+        self.fragment.as_deref()
+    }
+
+    /// Get the URL without its fragment
+    pub fn without_fragment(&self) -> Url {
+        Url {
+            fragment: None,
+            ..self.clone()
+        }
+    }
+
+    /// Whether the other URL is on the same server, with the same scheme, host and port. Client
+    /// IDs and anything else a server gave us over TLS only go to URLs on the same server.
+    pub fn same_server(&self, other: &Url) -> bool {
+        self.scheme == other.scheme && self.host == other.host && self.port() == other.port()
+    }
+
+    /// Get the request string to send to the server, which is the path without the leading `/`
+    pub fn request(&self) -> &str {
+        &self.path[1..]
+    }
+
+    /// Get the host and port, suitable for `TcpStream::connect`
+    pub fn socket_addr(&self) -> (&str, u16) {
+        // strip the brackets from IPv6 literals
+        let host = self.host.trim_start_matches('[').trim_end_matches(']');
+        (host, self.port())
+    }
+
+    /// Resolve a link target relative to this URL.
+    ///
+    /// Absolute URLs are parsed as they are. `//host/path` keeps the scheme, `/path` keeps the
+    /// host, `#anchor` keeps everything but the fragment, and anything else is relative to the
+    /// directory of this URL's path.
+    pub fn resolve(&self, link: &str) -> Result<Url, FroggiError> {
+        if link.contains("://") {
+            return Url::parse(link);
+        }
+
+        if let Some(rest) = link.strip_prefix("//") {
+            return Url::parse(&format!("{}://{}", self.scheme.as_str(), rest));
+        }
+
+        let (link, fragment) = split_fragment(link);
+        let path = if link.is_empty() {
+            self.path.clone()
+        } else if link.starts_with('/') {
+            normalize_path(link)
+        } else {
+            // unwrap safety - the path always starts with /
+            let dir = &self.path[..=self.path.rfind('/').unwrap()];
+            normalize_path(&format!("{}{}", dir, link))
+        };
+
+        Ok(Url {
+            scheme: self.scheme,
+            host: self.host.clone(),
+            port: self.port,
+            path,
+            fragment: fragment.map(String::from),
+        })
+    }
+
+    /// Resolve the target of a link expression relative to this URL.
+    ///
+    /// Returns `None` if the payload is not a link.
+    #[cfg(feature = "markup")]
+    pub fn resolve_link(
+        &self,
+        payload: &crate::markup::ExpressionPayload,
+    ) -> Option<Result<Url, FroggiError>> {
+        match payload {
+            crate::markup::ExpressionPayload::Link { link, .. } => {
+                Some(self.resolve(link.lexeme()))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Url {
+    type Err = FroggiError;
+
+    fn from_str(s: &str) -> Result<Url, FroggiError> {
+        Url::parse(s)
+    }
+}
+
+impl fmt::Display for Url {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}://{}", self.scheme.as_str(), self.host)?;
+        if let Some(port) = self.port {
+            write!(f, ":{}", port)?;
+        }
+        write!(f, "{}", self.path)?;
+        if let Some(fragment) = &self.fragment {
+            write!(f, "#{}", fragment)?;
+        }
+        Ok(())
+    }
+}
+
+fn url_error(error: UrlError) -> FroggiError {
+    FroggiError::new(ErrorKind::UrlError { error })
+}
+
+fn split_fragment(s: &str) -> (&str, Option<&str>) {
+    match s.find('#') {
+        Some(i) => (&s[..i], Some(&s[i + 1..])),
+        None => (s, None),
+    }
+}
+
+fn split_port(authority: &str) -> Result<(&str, Option<u16>), FroggiError> {
+    // IPv6 literals have colons of their own
+    let colon = if authority.starts_with('[') {
+        authority
+            .find(']')
+            .and_then(|i| authority[i..].find(':').map(|j| i + j))
+    } else {
+        authority.rfind(':')
+    };
+
+    match colon {
+        Some(i) => {
+            let port = &authority[i + 1..];
+            let port = port
+                .parse()
+                .map_err(|_| url_error(UrlError::InvalidPort { port: port.into() }))?;
+            Ok((&authority[..i], Some(port)))
+        }
+        None => Ok((authority, None)),
+    }
+}
+
+// remove . and .. segments, keeping a trailing slash if there was one
+fn normalize_path(path: &str) -> String {
+    let parts = path.split('/').collect::<Vec<_>>();
+    let mut segments = Vec::new();
+
+    for part in parts.iter() {
+        match *part {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+
+    let trailing_slash = matches!(parts.last(), Some(&"") | Some(&".") | Some(&".."));
+
+    let mut normalized = String::from("/");
+    normalized.push_str(&segments.join("/"));
+    if trailing_slash && !segments.is_empty() {
+        normalized.push('/');
+    }
+
+    normalized
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let url = Url::parse("frgi://example.com/test_markup.fml#anchor").unwrap();
+        assert_eq!(url.scheme(), Scheme::Frgi);
+        assert_eq!(url.host(), "example.com");
+        assert_eq!(url.port(), DEFAULT_PORT);
+        assert_eq!(url.path(), "/test_markup.fml");
+        assert_eq!(url.request(), "test_markup.fml");
+        assert_eq!(url.fragment(), Some("anchor"));
+
+        let url = Url::parse("frgi://www.lipsum.com/").unwrap();
+        assert_eq!(url.path(), "/");
+        assert_eq!(url.request(), "");
+        assert_eq!(url.fragment(), None);
+
+        let url = Url::parse("FRGIS://Example.com:1234").unwrap();
+        assert_eq!(url.scheme(), Scheme::Frgis);
+        assert_eq!(url.host(), "example.com");
+        assert_eq!(url.port(), 1234);
+        assert_eq!(url.path(), "/");
+
+        let url = Url::parse("frgi://[::1]:11122/a/b.fml").unwrap();
+        assert_eq!(url.host(), "[::1]");
+        assert_eq!(url.socket_addr(), ("::1", 11122));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let kind = |url| match Url::parse(url).unwrap_err().kind() {
+            ErrorKind::UrlError { error } => match error {
+                UrlError::NotAbsolute { .. } => "absolute",
+                UrlError::UnknownScheme { .. } => "scheme",
+                UrlError::MissingHost { .. } => "host",
+                UrlError::InvalidPort { .. } => "port",
+                _ => panic!(),
+            },
+            _ => panic!(),
+        };
+
+        assert_eq!(kind("example.com/index.fml"), "absolute");
+        assert_eq!(kind("https://example.com/"), "scheme");
+        assert_eq!(kind("frgi:///index.fml"), "host");
+        assert_eq!(kind("frgi://example.com:froggi/"), "port");
+        assert_eq!(kind("frgi://example.com:99999/"), "port");
+    }
+
+    #[test]
+    fn display() {
+        for url in &[
+            "frgi://example.com/test_markup.fml#anchor",
+            "frgis://example.com:1234/",
+            "frgi://[::1]:11122/a/b.fml",
+        ] {
+            assert_eq!(&Url::parse(url).unwrap().to_string(), url);
+        }
+    }
+
+    #[test]
+    fn resolve() {
+        let base = Url::parse("frgi://example.com:1234/docs/pages/index.fml#top").unwrap();
+        let resolve = |link| base.resolve(link).unwrap().to_string();
+
+        assert_eq!(
+            resolve("other.fml"),
+            "frgi://example.com:1234/docs/pages/other.fml"
+        );
+        assert_eq!(
+            resolve("./other.fml"),
+            "frgi://example.com:1234/docs/pages/other.fml"
+        );
+        assert_eq!(
+            resolve("../up.fml#a"),
+            "frgi://example.com:1234/docs/up.fml#a"
+        );
+        assert_eq!(
+            resolve("../../../../root.fml"),
+            "frgi://example.com:1234/root.fml"
+        );
+        assert_eq!(resolve("sub/"), "frgi://example.com:1234/docs/pages/sub/");
+        assert_eq!(resolve(".."), "frgi://example.com:1234/docs/");
+        assert_eq!(resolve("/abs.fml"), "frgi://example.com:1234/abs.fml");
+        assert_eq!(
+            resolve("#anchor"),
+            "frgi://example.com:1234/docs/pages/index.fml#anchor"
+        );
+        assert_eq!(resolve(""), "frgi://example.com:1234/docs/pages/index.fml");
+        assert_eq!(resolve("//other.com/x.fml"), "frgi://other.com/x.fml");
+        assert_eq!(resolve("frgis://secure.com/"), "frgis://secure.com/");
+
+        assert!(base.resolve("https://zphixon.com/lemon").is_err());
+    }
+
+    #[cfg(feature = "markup")]
+    #[test]
+    fn resolve_link() {
+        let page =
+            crate::markup::parse::parse(r#"(^ "test_markup.fml#anchor" "text") ("not a link")"#)
+                .unwrap();
+        let base = Url::parse("frgi://example.com/index.fml").unwrap();
+
+        assert_eq!(
+            base.resolve_link(&page.expressions[0].payload)
+                .unwrap()
+                .unwrap()
+                .to_string(),
+            "frgi://example.com/test_markup.fml#anchor"
+        );
+        assert!(base.resolve_link(&page.expressions[1].payload).is_none());
+    }
+}
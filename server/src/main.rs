@@ -1,57 +1,348 @@
+mod put;
+mod session;
+mod slots;
+
+use put::PutHandlers;
+use session::{SessionStore, MAX_SESSIONS, SESSION_TTL};
+use slots::{Slots, MAX_CONNECTIONS};
+
 use anyhow::{Context, Result};
-use froggi::request::Request;
-use froggi::response::{Item, ItemKind, Response, ResponseBuilder, ResponseKind};
+use froggi::connection::IDLE_TIMEOUT;
+use froggi::hash::ContentHash;
+use froggi::request::{Request, RequestKind};
+use froggi::response::{CachedResponse, ErrorCode, Item, Response, ResponseBuilder, ResponseKind};
+use froggi::tls::rustls::{ServerConfig, ServerConnection, StreamOwned};
+use froggi::transport::{Listener, Stream};
+use froggi::{ErrorKind, FroggiError};
 
 use std::collections::HashMap;
-use std::io::Write;
-use std::net::{TcpListener, TcpStream};
+use std::io::{BufWriter, Read, Write};
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::sync::Arc;
+use std::thread;
+
+// answer requests until the client stops asking to keep the connection alive
+fn handle_client(
+    mut stream: impl Read + Write,
+    page_store: &PageStore,
+    puts: &PutHandlers,
+    sessions: &SessionStore,
+) -> Result<()> {
+    let mut first = true;
+    loop {
+        let request = match Request::from_bytes(&mut stream) {
+            Ok(request) => request,
+
+            // the client is done with the connection, or it timed out
+            Err(error) if !first && matches!(error.kind(), ErrorKind::IOError { .. }) => {
+                return Ok(());
+            }
+
+            Err(error) => {
+                // no point responding if the connection is broken
+                if let Some((code, version)) = request_error_code(&error) {
+                    ResponseBuilder::default()
+                        .version(version)
+                        .page("('bad request')".into())
+                        .error(code)
+                        .build()
+                        .unwrap()
+                        .write_to(&mut stream)?;
+                    stream.flush()?;
+                }
+                return Err(error.into());
+            }
+        };
+        first = false;
+
+        println!("request: {:?}", request);
+        if let Some(user_agent) = request.user_agent() {
+            println!("user agent: {}", user_agent);
+        }
+
+        let mut writer = BufWriter::new(&mut stream);
+        respond(&request, &mut writer, page_store, puts, sessions)?;
+        writer.flush()?;
+
+        if !request.keep_alive() {
+            return Ok(());
+        }
+    }
+}
+
+fn respond(
+    request: &Request,
+    writer: &mut impl Write,
+    page_store: &PageStore,
+    puts: &PutHandlers,
+    sessions: &SessionStore,
+) -> Result<()> {
+    match sessions.client_id(request) {
+        Ok(id) => {
+            if let Some(page) = sessions.with_session(id, |session| session.page().to_string()) {
+                println!("client {} is viewing {}", id, page);
+            }
+
+            match request.kind() {
+                RequestKind::Put => match puts.handle(request) {
+                    Some(Ok(response)) => response.write_for(request, id, writer)?,
+                    Some(Err(error)) => {
+                        println!("put handler failed: {}", error);
+                        error_response(ErrorCode::ServerError, "something went wrong")
+                            .write_for(request, id, writer)?
+                    }
+                    None => page_store.not_found().write_for(request, id, writer)?,
+                },
+
+                RequestKind::Item => page_store
+                    .item_range(request)
+                    .write_for(request, id, writer)?,
+
+                // items the client already has are left out, and metadata requests only get the
+                // lengths and hashes of the page and items
+                _ => match page_store.fragment(request) {
+                    Some(fragment) => fragment.write_for(request, id, writer)?,
+                    None => page_store
+                        .response(request)
+                        .write_for(request, id, writer)?,
+                },
+            }
+        }
+
+        Err(invalid) => {
+            println!("invalid client ID {}", invalid.0);
+            let response = page_store.invalid_session();
+            response.write_for(request, response.response().id(), writer)?;
+        }
+    }
+
+    Ok(())
+}
+
+// which error code to respond to a request we couldn't read with, and which version to respond
+// in. clients that are too new are told the newest version we speak, so they can try it, and
+// everyone else gets the oldest, which every client can read
+fn request_error_code(error: &FroggiError) -> Option<(ErrorCode, u8)> {
+    let oldest = froggi::FROGGI_VERSIONS[0];
+    match error.kind() {
+        ErrorKind::IOError { .. } => None,
+        ErrorKind::VersionError { .. } => {
+            Some((ErrorCode::UnsupportedVersion, froggi::FROGGI_VERSION))
+        }
+        ErrorKind::LimitError { .. } => Some((ErrorCode::TooLarge, oldest)),
+        _ => Some((ErrorCode::BadRequest, oldest)),
+    }
+}
+
+fn error_response(code: ErrorCode, message: &str) -> Response {
+    ResponseBuilder::default()
+        .page(format!("('{}')", message))
+        .error(code)
+        .build()
+        .unwrap()
+}
+
+// usage: froggi-server [--tls cert.pem key.pem] [--unix path]
+fn tls_config() -> Result<Option<Arc<ServerConfig>>> {
+    let args = std::env::args().collect::<Vec<_>>();
+    match args.iter().position(|arg| arg == "--tls") {
+        Some(i) => {
+            let cert = args.get(i + 1).context("--tls needs a certificate file")?;
+            let key = args.get(i + 2).context("--tls needs a private key file")?;
 
-fn handle_client(mut stream: TcpStream, page_store: &PageStore) {
-    let request = Request::from_bytes(&mut stream).unwrap();
+            let chain = froggi::tls::load_certificates(cert)?;
+            let key = froggi::tls::load_private_key(key)?;
 
-    println!("request: {:?}", request);
+            Ok(Some(froggi::tls::server_config(chain, key)?))
+        }
 
-    match page_store.page(request.request()) {
-        Some(page) => stream.write_all(page).unwrap(),
-        None => stream.write_all(page_store.not_found()).unwrap(),
+        None => Ok(None),
     }
 }
 
-// TODO we're accidentally caching the ID
+// listen on a unix socket at this path instead of TCP
+#[cfg(unix)]
+fn unix_socket_path() -> Result<Option<String>> {
+    let args = std::env::args().collect::<Vec<_>>();
+    match args.iter().position(|arg| arg == "--unix") {
+        Some(i) => Ok(Some(
+            args.get(i + 1)
+                .context("--unix needs a socket path")?
+                .clone(),
+        )),
+        None => Ok(None),
+    }
+}
+
+// pages are compressed once when they're loaded, rather than for every client, and so are the
+// parts of them at each of their anchors
+struct CachedPage {
+    response: CachedResponse,
+    hash: ContentHash,
+    fragments: HashMap<String, CachedPage>,
+}
+
+impl CachedPage {
+    fn new(response: Response, fragments: HashMap<String, CachedPage>) -> CachedPage {
+        CachedPage {
+            hash: response.content_hash(),
+            response: cached(response),
+            fragments,
+        }
+    }
+}
+
+// the part of a page at each of its anchors, with only the items that part references.
+// redirects and pages that aren't markup don't have any
+fn fragments(response: &Response) -> HashMap<String, CachedPage> {
+    if response.kind() == ResponseKind::Redirect {
+        return HashMap::new();
+    }
+
+    let page = match froggi::markup::parse::parse(response.page()) {
+        Ok(page) => page,
+        Err(_) => return HashMap::new(),
+    };
+
+    page.anchors()
+        .into_iter()
+        .filter_map(|anchor| {
+            let fragment = page.fragment(&anchor)?;
+            let item_names = fragment.item_names();
+            let builder = ResponseBuilder::default().page(froggi::markup::to_markup(&fragment));
+
+            // unwrap safety - the page and items were already in a response
+            let fragment = response
+                .items()
+                .iter()
+                .filter(|item| item_names.iter().any(|name| name == item.name()))
+                .fold(builder, |builder, item| builder.item(item.clone()))
+                .build()
+                .unwrap();
+
+            Some((anchor, CachedPage::new(fragment, HashMap::new())))
+        })
+        .collect()
+}
+
 struct PageStore {
-    page_cache: HashMap<String, Vec<u8>>,
-    not_found: Vec<u8>,
+    page_cache: HashMap<String, CachedPage>,
+    not_found: CachedResponse,
+    no_such_anchor: CachedResponse,
+    not_modified: CachedResponse,
+    invalid_session: CachedResponse,
+}
+
+// unwrap safety - compressing only fails for codecs we don't support
+fn cached(response: Response) -> CachedResponse {
+    CachedResponse::new(response).unwrap()
 }
 
 impl PageStore {
     fn new() -> PageStore {
         PageStore {
             page_cache: HashMap::new(),
-            not_found: ResponseBuilder::default()
-                .page(String::from("('not found')"))
-                .kind(ResponseKind::Error)
-                .build()
-                .unwrap()
-                .bytes(),
+            not_found: cached(error_response(ErrorCode::NotFound, "not found")),
+            no_such_anchor: cached(error_response(ErrorCode::NotFound, "no such anchor")),
+            not_modified: cached(ResponseBuilder::default().not_modified().build().unwrap()),
+            invalid_session: cached(error_response(
+                ErrorCode::InvalidClientId,
+                "unknown or expired client ID",
+            )),
         }
     }
 
     fn add_page(&mut self, name: String, response: Response) {
-        self.page_cache.insert(name, response.bytes());
+        let fragments = fragments(&response);
+        self.page_cache
+            .insert(name, CachedPage::new(response, fragments));
     }
 
-    fn page(&self, request: &str) -> Option<&Vec<u8>> {
-        self.page_cache.get(request)
+    // one redirect per line, the old path followed by where it went. lines starting with # are
+    // comments
+    fn add_redirects(&mut self, redirects: &str) {
+        for line in redirects.lines() {
+            if line.trim_start().starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.split_whitespace();
+            if let (Some(from), Some(to)) = (parts.next(), parts.next()) {
+                println!("{} -> {}", from, to);
+                self.add_page(
+                    from.into(),
+                    ResponseBuilder::default().redirect(to).build().unwrap(),
+                );
+            }
+        }
     }
 
-    fn not_found(&self) -> &[u8] {
+    // the response to a page request, if the client doesn't already have it
+    fn response(&self, request: &Request) -> &CachedResponse {
+        match self.page_cache.get(request.path()) {
+            Some(page) if request.cached_hash() == Some(page.hash) => &self.not_modified,
+            Some(page) => &page.response,
+            None => &self.not_found,
+        }
+    }
+
+    // the part of a page at the anchor the client asked for, if the client doesn't already have
+    // it. requests without an anchor, for pages we don't have, and for redirects get the usual
+    // response
+    fn fragment(&self, request: &Request) -> Option<&CachedResponse> {
+        let anchor = request.fragment()?;
+        let page = self.page_cache.get(request.path())?;
+        if page.response.response().kind() == ResponseKind::Redirect {
+            return None;
+        }
+
+        Some(match page.fragments.get(anchor) {
+            Some(fragment) if request.cached_hash() == Some(fragment.hash) => &self.not_modified,
+            Some(fragment) => &fragment.response,
+            None => &self.no_such_anchor,
+        })
+    }
+
+    // part of one of a page's items
+    fn item_range(&self, request: &Request) -> Response {
+        let item = self.page_cache.get(request.path()).and_then(|page| {
+            page.response
+                .response()
+                .items()
+                .iter()
+                .find(|item| item.name() == request.item_name())
+        });
+
+        // unwrap safety - the offset is checked first
+        match item {
+            Some(item) if request.item_offset() <= item.data().len() => ResponseBuilder::default()
+                .item_range(item, request.item_offset(), request.item_len())
+                .build()
+                .unwrap(),
+            Some(_) => error_response(ErrorCode::BadRange, "the item isn't that long"),
+            None => error_response(ErrorCode::NotFound, "not found"),
+        }
+    }
+
+    fn not_found(&self) -> &CachedResponse {
         &self.not_found
     }
+
+    fn invalid_session(&self) -> &CachedResponse {
+        &self.invalid_session
+    }
 }
 
 fn main() {
+    let tls = tls_config().unwrap();
+    let sessions = Arc::new(SessionStore::new(SESSION_TTL, MAX_SESSIONS));
     let mut pages = PageStore::new();
 
+    let mut puts = PutHandlers::new();
+    puts.add("echo", put::echo);
+
     println!("reading pages");
     for item in std::fs::read_dir("pages").unwrap() {
         let item = item.unwrap();
@@ -66,22 +357,76 @@ fn main() {
         }
     }
 
+    if let Ok(redirects) = std::fs::read_to_string("pages/redirects") {
+        println!("reading redirects");
+        pages.add_redirects(&redirects);
+    }
+
+    let pages = Arc::new(pages);
+    let puts = Arc::new(puts);
+    let slots = Slots::new(MAX_CONNECTIONS);
+
+    let with_tls = if tls.is_some() { " with TLS" } else { "" };
+
+    #[cfg(unix)]
+    if let Some(path) = unix_socket_path().unwrap() {
+        let listener = UnixListener::bind(&path).unwrap();
+        println!("listening at {}{}", path, with_tls);
+        serve(listener, &tls, &pages, &puts, &sessions, &slots);
+    }
+
     let listener = TcpListener::bind("0.0.0.0:11121").unwrap();
     println!(
-        "listening at {}. run this binary from froggi-server dir!",
-        listener.local_addr().unwrap()
+        "listening at {}{}. run this binary from froggi-server dir!",
+        listener.local_addr().unwrap(),
+        with_tls,
     );
+    serve(listener, &tls, &pages, &puts, &sessions, &slots);
+}
 
-    for stream in listener.incoming() {
-        match stream {
+fn serve(
+    listener: impl Listener,
+    tls: &Option<Arc<ServerConfig>>,
+    pages: &Arc<PageStore>,
+    puts: &Arc<PutHandlers>,
+    sessions: &Arc<SessionStore>,
+    slots: &Arc<Slots>,
+) -> ! {
+    // every client gets its own thread, so idle connections don't hold anyone else up. there are
+    // only so many threads though, so new clients wait in the listen queue once they're all busy
+    loop {
+        let slot = slots.take();
+        match listener.accept() {
             Ok(stream) => {
                 println!("new client");
-                crossbeam::scope(|s| {
-                    s.spawn(|_| {
-                        handle_client(stream, &pages);
-                    });
-                })
-                .unwrap();
+                let tls = tls.clone();
+                let pages = Arc::clone(pages);
+                let puts = Arc::clone(puts);
+                let sessions = Arc::clone(sessions);
+
+                thread::spawn(move || {
+                    let _slot = slot;
+                    let result = stream
+                        .set_timeouts(Some(IDLE_TIMEOUT), None)
+                        .map_err(anyhow::Error::from)
+                        .and_then(|_| match tls {
+                            Some(config) => ServerConnection::new(config)
+                                .map_err(anyhow::Error::from)
+                                .and_then(|connection| {
+                                    handle_client(
+                                        StreamOwned::new(connection, stream),
+                                        &pages,
+                                        &puts,
+                                        &sessions,
+                                    )
+                                }),
+                            None => handle_client(stream, &pages, &puts, &sessions),
+                        });
+
+                    if let Err(e) = result {
+                        println!("error {}", e);
+                    }
+                });
             }
             Err(e) => {
                 println!("error {}", e);
@@ -112,7 +457,7 @@ fn response_from_file(path: impl AsRef<std::path::Path>) -> Result<Response> {
     let items = item_names
         .into_iter()
         .zip(item_data.into_iter())
-        .map(|(name, data)| Item::new(name, ItemKind::Image, data))
+        .map(|(name, data)| Item::sniffed(name, data))
         .collect();
 
     ResponseBuilder::default()
@@ -121,3 +466,156 @@ fn response_from_file(path: impl AsRef<std::path::Path>) -> Result<Response> {
         .build()
         .map_err(|e| anyhow::anyhow!(e))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use froggi::request::RequestKind;
+    use froggi::response::ItemKind;
+    use froggi::transport::memory;
+    use froggi::url::Url;
+    use froggi::Client;
+
+    #[test]
+    fn cached_fragments() {
+        let mut pages = PageStore::new();
+        pages.add_page(
+            "pond.fml".into(),
+            ResponseBuilder::default()
+                .page("(# \"lily\") (& \"lily.png\") (# \"frog\") (\"ribbit\")".into())
+                .item(Item::new(
+                    "lily.png".into(),
+                    ItemKind::Image,
+                    b"lily".to_vec(),
+                ))
+                .build()
+                .unwrap(),
+        );
+
+        // the same response every time, without the items it doesn't use
+        let request = Request::new("pond.fml#frog", RequestKind::PageItems).unwrap();
+        let frog = pages.fragment(&request).unwrap();
+        assert!(std::ptr::eq(frog, pages.fragment(&request).unwrap()));
+        assert_eq!(frog.response().page(), "(# \"frog\")\n(\"ribbit\")\n");
+        assert!(frog.response().items().is_empty());
+
+        let request = request.with_cached_hash(frog.response().content_hash());
+        assert_eq!(
+            pages.fragment(&request).unwrap().response().kind(),
+            ResponseKind::NotModified
+        );
+
+        let request = Request::new("pond.fml#toad", RequestKind::PageItems).unwrap();
+        assert_eq!(
+            pages.fragment(&request).unwrap().response().error_code(),
+            Some(ErrorCode::NotFound)
+        );
+    }
+
+    #[test]
+    fn serve_in_memory() {
+        let mut pages = PageStore::new();
+        pages.add_page(
+            "index.fml".into(),
+            ResponseBuilder::default()
+                .page("('hello')".into())
+                .build()
+                .unwrap(),
+        );
+        pages.add_page(
+            "items.fml".into(),
+            ResponseBuilder::default()
+                .page("(img \"frog.png\")".into())
+                .item(Item::new(
+                    "frog.png".into(),
+                    ItemKind::Image,
+                    b"frog".to_vec(),
+                ))
+                .build()
+                .unwrap(),
+        );
+        pages.add_page(
+            "pond.fml".into(),
+            ResponseBuilder::default()
+                .page("(# \"lily\") (& \"lily.png\") (# \"frog\") (& \"frog.png\")".into())
+                .item(Item::new(
+                    "lily.png".into(),
+                    ItemKind::Image,
+                    b"lily".to_vec(),
+                ))
+                .item(Item::new(
+                    "frog.png".into(),
+                    ItemKind::Image,
+                    b"frog".to_vec(),
+                ))
+                .build()
+                .unwrap(),
+        );
+        pages.add_redirects("old.fml index.fml");
+        let puts = PutHandlers::new();
+        let sessions = SessionStore::new(SESSION_TTL, MAX_SESSIONS);
+
+        let (transport, listener) = memory();
+        let client = Client::builder().transport(transport).build().unwrap();
+
+        crossbeam::scope(|s| {
+            s.spawn(|_| {
+                // stops once the client is gone
+                while let Ok(stream) = listener.accept() {
+                    handle_client(stream, &pages, &puts, &sessions).unwrap();
+                }
+            });
+
+            let url = Url::parse("frgi://localhost/old.fml").unwrap();
+            let (url, response) = client.get(&url, RequestKind::PageOnly).unwrap();
+            assert_eq!(url.path(), "/index.fml");
+            assert_eq!(response.page(), "('hello')");
+
+            let (_, response) = client.get(&url, RequestKind::Metadata).unwrap();
+            assert!(response.is_metadata());
+            assert_eq!(response.page(), "");
+            assert_eq!(response.page_len(), "('hello')".len());
+
+            let url = Url::parse("frgi://localhost/items.fml").unwrap();
+            let (_, response) = client.get_item(&url, "frog.png", 1, None).unwrap();
+            assert_eq!(response.items()[0].data(), b"rog");
+            assert!(matches!(
+                client
+                    .get_item(&url, "frog.png", 5, None)
+                    .unwrap_err()
+                    .kind(),
+                ErrorKind::StatusError {
+                    code: ErrorCode::BadRange
+                }
+            ));
+
+            let url = Url::parse("frgi://localhost/pond.fml#frog").unwrap();
+            let (_, response) = client.get_fragment(&url, RequestKind::PageItems).unwrap();
+            assert_eq!(response.page(), "(# \"frog\")\n(& \"frog.png\")\n");
+            assert_eq!(response.items().len(), 1);
+            assert_eq!(response.items()[0].data(), b"frog");
+
+            let url = Url::parse("frgi://localhost/pond.fml#toad").unwrap();
+            assert!(matches!(
+                client
+                    .get_fragment(&url, RequestKind::PageItems)
+                    .unwrap_err()
+                    .kind(),
+                ErrorKind::StatusError {
+                    code: ErrorCode::NotFound
+                }
+            ));
+
+            let url = Url::parse("frgi://localhost/nope.fml").unwrap();
+            assert!(matches!(
+                client.get(&url, RequestKind::PageOnly).unwrap_err().kind(),
+                ErrorKind::StatusError {
+                    code: ErrorCode::NotFound
+                }
+            ));
+
+            drop(client);
+        })
+        .unwrap();
+    }
+}
@@ -0,0 +1,80 @@
+//! Handlers for put requests, keyed by the path the client sends data to.
+
+use anyhow::Result;
+use froggi::request::Request;
+use froggi::response::{Item, Response, ResponseBuilder};
+
+use std::collections::HashMap;
+
+/// Something that responds to the data in a put request.
+pub type PutHandler = Box<dyn Fn(&Request) -> Result<Response> + Send + Sync>;
+
+/// The put handlers the server knows about.
+pub struct PutHandlers {
+    handlers: HashMap<String, PutHandler>,
+}
+
+impl PutHandlers {
+    pub fn new() -> PutHandlers {
+        PutHandlers {
+            handlers: HashMap::new(),
+        }
+    }
+
+    /// Handle put requests sent to a path.
+    pub fn add(
+        &mut self,
+        path: impl Into<String>,
+        handler: impl Fn(&Request) -> Result<Response> + Send + Sync + 'static,
+    ) {
+        self.handlers.insert(path.into(), Box::new(handler));
+    }
+
+    /// Respond to a put request, if there's a handler for its path.
+    pub fn handle(&self, request: &Request) -> Option<Result<Response>> {
+        self.handlers
+            .get(request.request())
+            .map(|handler| handler(request))
+    }
+}
+
+/// Send the body back to the client as an item.
+pub fn echo(request: &Request) -> Result<Response> {
+    Ok(ResponseBuilder::default()
+        .page(format!(
+            "(\"you sent {} bytes\") (& \"body\" \"what you sent\")",
+            request.body().len()
+        ))
+        .item(Item::new(
+            String::from("body"),
+            request.body_kind(),
+            request.body().to_vec(),
+        ))
+        .build()?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use froggi::request::RequestKind;
+    use froggi::response::ItemKind;
+    use froggi::Uuid;
+
+    #[test]
+    fn echo_handler() {
+        let mut handlers = PutHandlers::new();
+        handlers.add("echo", echo);
+
+        let request =
+            Request::new_put("echo", Uuid::nil(), ItemKind::Image, b"frg".to_vec()).unwrap();
+        let response = handlers.handle(&request).unwrap().unwrap();
+        assert_eq!(
+            response.page(),
+            "(\"you sent 3 bytes\") (& \"body\" \"what you sent\")"
+        );
+        assert_eq!(response.items()[0].data(), b"frg");
+
+        let request = Request::new("nothing.fml", RequestKind::Put).unwrap();
+        assert!(handlers.handle(&request).is_none());
+    }
+}
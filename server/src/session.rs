@@ -0,0 +1,211 @@
+//! Client sessions, keyed by the client ID the server hands out.
+
+use froggi::request::{Request, RequestKind};
+use froggi::Uuid;
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long a session lives without hearing from its client.
+pub const SESSION_TTL: Duration = Duration::from_secs(30 * 60);
+
+/// The most sessions kept at once. Past this, the least recently seen session is forgotten.
+pub const MAX_SESSIONS: usize = 64 * 1024;
+
+/// What the server remembers about a client.
+#[derive(Debug)]
+pub struct Session {
+    last_seen: Instant,
+    page: String,
+}
+
+impl Session {
+    /// The page the client most recently asked for
+    pub fn page(&self) -> &str {
+        &self.page
+    }
+}
+
+/// The client sent an ID we don't know about, or one that has expired.
+#[derive(Debug, PartialEq)]
+pub struct InvalidSession(pub Uuid);
+
+/// Sessions that are currently alive.
+pub struct SessionStore {
+    sessions: Mutex<Sessions>,
+    ttl: Duration,
+    max_sessions: usize,
+}
+
+#[derive(Default)]
+struct Sessions {
+    by_id: HashMap<Uuid, Session>,
+    // the same sessions, least recently seen first
+    by_age: BTreeSet<(Instant, Uuid)>,
+}
+
+impl Sessions {
+    // forget sessions we haven't heard from in a while. they're the oldest, so this stops at the
+    // first live one instead of looking at every session
+    fn expire(&mut self, now: Instant, ttl: Duration) {
+        while let Some(&(last_seen, id)) = self.by_age.first() {
+            if now.duration_since(last_seen) < ttl {
+                break;
+            }
+            self.remove(last_seen, id);
+        }
+    }
+
+    fn remove(&mut self, last_seen: Instant, id: Uuid) {
+        self.by_age.remove(&(last_seen, id));
+        self.by_id.remove(&id);
+    }
+
+    // start a session, or keep one alive
+    fn seen(&mut self, id: Uuid, now: Instant, page: &str) {
+        let session = self.by_id.entry(id).or_insert_with(|| Session {
+            last_seen: now,
+            page: String::new(),
+        });
+        self.by_age.remove(&(session.last_seen, id));
+        self.by_age.insert((now, id));
+
+        session.last_seen = now;
+        session.page = page.into();
+    }
+}
+
+impl SessionStore {
+    pub fn new(ttl: Duration, max_sessions: usize) -> SessionStore {
+        SessionStore {
+            sessions: Mutex::new(Sessions::default()),
+            ttl,
+            max_sessions,
+        }
+    }
+
+    /// Figure out which client ID to respond to a request with.
+    ///
+    /// Page requests get a fresh ID unless they already have a live one. Other requests don't
+    /// need an ID, but if they send one it has to be live.
+    pub fn client_id(&self, request: &Request) -> Result<Uuid, InvalidSession> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let now = Instant::now();
+        sessions.expire(now, self.ttl);
+
+        if sessions.by_id.contains_key(&request.id()) {
+            sessions.seen(request.id(), now, request.request());
+            return Ok(request.id());
+        }
+
+        match request.kind() {
+            RequestKind::Page => {
+                // make room by forgetting whoever we heard from least recently
+                while sessions.by_id.len() >= self.max_sessions {
+                    match sessions.by_age.first() {
+                        Some(&(last_seen, id)) => sessions.remove(last_seen, id),
+                        None => break,
+                    }
+                }
+
+                let id = Uuid::new_v4();
+                sessions.seen(id, now, request.request());
+                Ok(id)
+            }
+
+            _ if request.id().is_nil() => Ok(Uuid::nil()),
+            _ => Err(InvalidSession(request.id())),
+        }
+    }
+
+    /// Look at the state of a live session.
+    pub fn with_session<T>(&self, id: Uuid, f: impl FnOnce(&mut Session) -> T) -> Option<T> {
+        let mut sessions = self.sessions.lock().unwrap();
+        match sessions.by_id.get_mut(&id) {
+            Some(session) if session.last_seen.elapsed() < self.ttl => Some(f(session)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn page_mints_id() {
+        let store = SessionStore::new(SESSION_TTL, MAX_SESSIONS);
+
+        let request = Request::new("index.fml", RequestKind::Page).unwrap();
+        let id = store.client_id(&request).unwrap();
+        assert!(!id.is_nil());
+        assert_eq!(
+            store.with_session(id, |session| session.page().to_string()),
+            Some(String::from("index.fml"))
+        );
+
+        // a second client gets a different ID
+        assert_ne!(store.client_id(&request).unwrap(), id);
+
+        // the same client keeps its ID
+        let request = Request::new_with_id("other.fml", id, RequestKind::Page).unwrap();
+        assert_eq!(store.client_id(&request).unwrap(), id);
+        assert_eq!(
+            store.with_session(id, |session| session.page().to_string()),
+            Some(String::from("other.fml"))
+        );
+    }
+
+    #[test]
+    fn no_id_needed() {
+        let store = SessionStore::new(SESSION_TTL, MAX_SESSIONS);
+        let request = Request::new("index.fml", RequestKind::PageOnly).unwrap();
+        assert!(store.client_id(&request).unwrap().is_nil());
+    }
+
+    #[test]
+    fn unknown_id() {
+        let store = SessionStore::new(SESSION_TTL, MAX_SESSIONS);
+        let id = Uuid::new_v4();
+
+        let request = Request::new_with_id("index.fml", id, RequestKind::Put).unwrap();
+        assert_eq!(store.client_id(&request), Err(InvalidSession(id)));
+
+        // page requests just get a new one
+        let request = Request::new_with_id("index.fml", id, RequestKind::Page).unwrap();
+        let new_id = store.client_id(&request).unwrap();
+        assert_ne!(new_id, id);
+        assert!(!new_id.is_nil());
+    }
+
+    #[test]
+    fn expired_id() {
+        let store = SessionStore::new(Duration::from_secs(0), MAX_SESSIONS);
+
+        let request = Request::new("index.fml", RequestKind::Page).unwrap();
+        let id = store.client_id(&request).unwrap();
+        assert!(store.with_session(id, |_| ()).is_none());
+
+        let request = Request::new_with_id("index.fml", id, RequestKind::PageItems).unwrap();
+        assert_eq!(store.client_id(&request), Err(InvalidSession(id)));
+    }
+
+    #[test]
+    fn max_sessions() {
+        let store = SessionStore::new(SESSION_TTL, 2);
+        let page = |id| Request::new_with_id("index.fml", id, RequestKind::Page).unwrap();
+
+        let first = store.client_id(&page(Uuid::nil())).unwrap();
+        let second = store.client_id(&page(Uuid::nil())).unwrap();
+
+        // hearing from the first client again makes the second the oldest
+        assert_eq!(store.client_id(&page(first)).unwrap(), first);
+        let third = store.client_id(&page(Uuid::nil())).unwrap();
+
+        assert!(store.with_session(first, |_| ()).is_some());
+        assert!(store.with_session(second, |_| ()).is_none());
+        assert!(store.with_session(third, |_| ()).is_some());
+        assert_eq!(store.sessions.lock().unwrap().by_age.len(), 2);
+    }
+}
@@ -0,0 +1,82 @@
+//! A cap on how many connections are served at once.
+
+use std::sync::{Arc, Condvar, Mutex};
+
+/// The most connections served at once. Past this, new connections wait to be accepted until
+/// one of the others closes.
+pub const MAX_CONNECTIONS: usize = 256;
+
+/// Connection slots, one per connection being served.
+pub struct Slots {
+    used: Mutex<usize>,
+    freed: Condvar,
+    max: usize,
+}
+
+impl Slots {
+    pub fn new(max: usize) -> Arc<Slots> {
+        Arc::new(Slots {
+            used: Mutex::new(0),
+            freed: Condvar::new(),
+            max,
+        })
+    }
+
+    /// Take a slot, waiting for one to be freed if they're all in use.
+    pub fn take(self: &Arc<Self>) -> Slot {
+        let mut used = self.used.lock().unwrap();
+        while *used >= self.max {
+            used = self.freed.wait(used).unwrap();
+        }
+        *used += 1;
+
+        Slot {
+            slots: Arc::clone(self),
+        }
+    }
+}
+
+/// A slot that's in use. It's freed when dropped.
+pub struct Slot {
+    slots: Arc<Slots>,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        *self.slots.used.lock().unwrap() -= 1;
+        self.slots.freed.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn wait_for_free_slot() {
+        let slots = Slots::new(2);
+        let first = slots.take();
+        let _second = slots.take();
+        assert_eq!(*slots.used.lock().unwrap(), 2);
+
+        let (tx, rx) = mpsc::channel();
+        let waiting = {
+            let slots = Arc::clone(&slots);
+            thread::spawn(move || {
+                let _third = slots.take();
+                tx.send(()).unwrap();
+            })
+        };
+
+        // the third has to wait for the first to be freed
+        assert!(rx.recv_timeout(Duration::from_millis(50)).is_err());
+        drop(first);
+        rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        waiting.join().unwrap();
+        assert_eq!(*slots.used.lock().unwrap(), 1);
+    }
+}